@@ -0,0 +1,333 @@
+use std::collections::HashSet;
+use crate::types::{ast::*, debruijn::Term};
+
+// Convert expression to nameless representation
+pub fn to_debruijn(e: &Expression) -> Term {
+    convert(e, &mut Vec::new())
+}
+
+// Convert with the binders in scope, innermost last
+fn convert(e: &Expression, scope: &mut Vec<Ident>) -> Term {
+    match e {
+        Expression::ValExpr(Value::Identifier(x)) => {
+            // Index of innermost binder with the same name
+            match scope.iter().rev().position(|p| p.as_deref() == Some(x.as_str())) {
+                Some(i) => Term::Var(i),
+                None => Term::Free(x.clone())
+            }
+        },
+        Expression::ValExpr(Value::Number(n)) => Term::Num(*n),
+        Expression::ValExpr(Value::Boolean(b)) => Term::Bool(*b),
+        Expression::ValExpr(Value::Unit) => Term::Unit,
+        Expression::TupleExpr(elist) | Expression::ValExpr(Value::Tuple(elist)) => Term::Tuple(elist.iter().map(|ex| convert(ex, scope)).collect()),
+        Expression::ProjExpr(ex, i, _) => Term::Proj(Box::new(convert(ex.as_ref(), scope)), *i),
+        Expression::ListExpr(elist) | Expression::ValExpr(Value::List(elist)) => Term::List(elist.iter().map(|ex| convert(ex, scope)).collect()),
+        Expression::ConstructExpr(d, i, elist) | Expression::ValExpr(Value::Data(d, i, elist)) => {
+            Term::Construct(d.clone(), *i, elist.iter().map(|ex| convert(ex, scope)).collect())
+        },
+        Expression::MatchExpr(e1, cases) => {
+            let t1 = convert(e1.as_ref(), scope);
+            // Case variables are bound in their body, the last innermost
+            let tcases = cases.iter().map(|case| {
+                let n = scope.len();
+                scope.extend(case.vars.iter().cloned());
+                let t = convert(&case.body, scope);
+                scope.truncate(n);
+                (case.constructor.clone(), case.vars.len(), t)
+            }).collect();
+            Term::Match(Box::new(t1), tcases)
+        },
+        Expression::FuncExpr(params, _, body) | Expression::ValExpr(Value::Closure(params, body, _)) => {
+            // One lambda per parameter
+            let mut t = convert_under(params, body.as_ref(), scope);
+            for _ in params { t = Term::Lam(Box::new(t)) };
+            t
+        },
+        Expression::ApplicationExpr(alist) => {
+            // Application chain associates to the left
+            let mut t = convert(&alist[0], scope);
+            for ex in alist[1..].iter() { t = Term::App(Box::new(t), Box::new(convert(ex, scope))) };
+            t
+        },
+        Expression::LetExpr(id, e1, body) => {
+            let t1 = convert(e1.as_ref(), scope);
+            // Pattern is bound in body only
+            let t2 = convert_under(std::slice::from_ref(id), body.as_ref(), scope);
+            Term::Let(Box::new(t1), Box::new(t2))
+        },
+        Expression::FixExpr(flist, i) => Term::Fix(flist.iter().map(|ex| convert(ex, scope)).collect(), *i),
+        Expression::IfExpr(c, t, f) => Term::If(
+            Box::new(convert(c.as_ref(), scope)),
+            Box::new(convert(t.as_ref(), scope)),
+            Box::new(convert(f.as_ref(), scope))
+        ),
+        Expression::UopExpr(op, e1) => Term::Uop(op.clone(), Box::new(convert(e1.as_ref(), scope))),
+        Expression::BopExpr(op, e1, e2) => Term::Bop(op.clone(), Box::new(convert(e1.as_ref(), scope)), Box::new(convert(e2.as_ref(), scope))),
+        // Types are erased
+        Expression::SpanExpr(_, ex) | Expression::EnvExpr(_, ex) | Expression::TypeFuncExpr(_, ex) | Expression::TypeAppExpr(ex, _) | Expression::DeclExpr(_, ex) => convert(ex.as_ref(), scope),
+        Expression::ThunkExpr(cell) => convert(&cell.borrow(), scope),
+        Expression::ErrorExpr => Term::Error
+    }
+}
+
+// Convert body under one binder per pattern, a tuple pattern binds an unnamed value whose components are bound by lets
+fn convert_under(patterns: &[Pattern], body: &Expression, scope: &mut Vec<Ident>) -> Term {
+    let n = scope.len();
+    // Unnamed tuple values still to destructure, by position in scope
+    let mut pending = Vec::new();
+    for p in patterns {
+        if let Pattern::Tuple(plist) = p { pending.push((scope.len(), plist)) }
+        scope.push(pattern_binder(p));
+    }
+    // Projections bound by lets, outermost first
+    let mut projs = Vec::new();
+    let mut k = 0;
+    while k < pending.len() {
+        let (pos, plist) = pending[k];
+        for (i, q) in plist.iter().enumerate() {
+            projs.push(Term::Proj(Box::new(Term::Var(scope.len() - 1 - pos)), i));
+            if let Pattern::Tuple(qlist) = q { pending.push((scope.len(), qlist)) }
+            scope.push(pattern_binder(q));
+        }
+        k += 1;
+    }
+    let t = convert(body, scope);
+    scope.truncate(n);
+    projs.into_iter().rev().fold(t, |acc, t1| Term::Let(Box::new(t1), Box::new(acc)))
+}
+
+// Name bound by a pattern itself, none for a tuple
+fn pattern_binder(p: &Pattern) -> Ident {
+    match p {
+        Pattern::Var(id) => id.clone(),
+        Pattern::Tuple(_) => None
+    }
+}
+
+// Collect names of free variables
+fn free_names(t: &Term, out: &mut HashSet<String>) {
+    match t {
+        Term::Free(x) => { out.insert(x.clone()); },
+        Term::Lam(t1) | Term::Uop(_, t1) | Term::Proj(t1, _) => free_names(t1, out),
+        Term::App(t1, t2) | Term::Let(t1, t2) | Term::Bop(_, t1, t2) => {
+            free_names(t1, out);
+            free_names(t2, out);
+        },
+        Term::If(t1, t2, t3) => {
+            free_names(t1, out);
+            free_names(t2, out);
+            free_names(t3, out);
+        },
+        Term::Fix(tlist, _) | Term::Tuple(tlist) | Term::List(tlist) | Term::Construct(_, _, tlist) => for t1 in tlist { free_names(t1, out) },
+        Term::Match(t1, tcases) => {
+            free_names(t1, out);
+            for (_, _, t2) in tcases { free_names(t2, out) }
+        },
+        _ => ()
+    }
+}
+
+// Convert nameless term back to an expression, naming binders by depth
+pub fn from_debruijn(t: &Term) -> Expression {
+    let mut free = HashSet::new();
+    free_names(t, &mut free);
+    restore(t, &mut Vec::new(), &free)
+}
+
+// Name for a new binder, different from free variables and binders in scope
+fn binder_name(names: &[String], free: &HashSet<String>) -> String {
+    let mut name = format!("x{}", names.len());
+    while free.contains(&name) || names.contains(&name) { name.push('\'') }
+    name
+}
+
+// Restore with the names of binders in scope, innermost last
+fn restore(t: &Term, names: &mut Vec<String>, free: &HashSet<String>) -> Expression {
+    match t {
+        Term::Var(i) => {
+            // Index beyond the binders in scope has no name, keep the index
+            let name = match names.len().checked_sub(i + 1) {
+                Some(j) => names[j].clone(),
+                None => format!("#{}", i)
+            };
+            Expression::ValExpr(Value::Identifier(name))
+        },
+        Term::Free(x) => Expression::ValExpr(Value::Identifier(x.clone())),
+        Term::Num(n) => Expression::ValExpr(Value::Number(*n)),
+        Term::Bool(b) => Expression::ValExpr(Value::Boolean(*b)),
+        Term::Unit => Expression::ValExpr(Value::Unit),
+        Term::Lam(_) => {
+            // Consecutive lambdas become one function of several parameters
+            let n = names.len();
+            let mut params = Vec::new();
+            let mut body = t;
+            while let Term::Lam(t1) = body {
+                let name = binder_name(names, free);
+                names.push(name.clone());
+                params.push(Pattern::Var(Some(name)));
+                body = t1.as_ref();
+            }
+            let ex = restore(body, names, free);
+            names.truncate(n);
+            let types = vec![None; params.len()];
+            Expression::FuncExpr(params, types, Box::new(ex))
+        },
+        Term::App(_, _) => {
+            // Left nested applications become one application chain
+            let mut alist = Vec::new();
+            let mut head = t;
+            while let Term::App(t1, t2) = head {
+                alist.push(restore(t2, names, free));
+                head = t1.as_ref();
+            }
+            alist.push(restore(head, names, free));
+            alist.reverse();
+            Expression::ApplicationExpr(alist)
+        },
+        Term::Let(t1, t2) => {
+            let e1 = restore(t1, names, free);
+            let name = binder_name(names, free);
+            names.push(name.clone());
+            let e2 = restore(t2, names, free);
+            names.pop();
+            Expression::LetExpr(Pattern::Var(Some(name)), Box::new(e1), Box::new(e2))
+        },
+        Term::Fix(tlist, i) => Expression::FixExpr(tlist.iter().map(|t1| restore(t1, names, free)).collect(), *i),
+        Term::Tuple(tlist) => Expression::TupleExpr(tlist.iter().map(|t1| restore(t1, names, free)).collect()),
+        Term::List(tlist) => Expression::ListExpr(tlist.iter().map(|t1| restore(t1, names, free)).collect()),
+        Term::Proj(t1, i) => Expression::ProjExpr(Box::new(restore(t1, names, free)), *i, None),
+        Term::Construct(d, i, tlist) => Expression::ConstructExpr(d.clone(), *i, tlist.iter().map(|t1| restore(t1, names, free)).collect()),
+        Term::Match(t1, tcases) => {
+            let e1 = restore(t1, names, free);
+            let cases = tcases.iter().map(|(constructor, nvars, t2)| {
+                let n = names.len();
+                let mut vars = Vec::new();
+                for _ in 0..*nvars {
+                    let name = binder_name(names, free);
+                    names.push(name.clone());
+                    vars.push(Some(name));
+                }
+                let body = restore(t2, names, free);
+                names.truncate(n);
+                Case { constructor: constructor.clone(), vars, body }
+            }).collect();
+            Expression::MatchExpr(Box::new(e1), cases)
+        },
+        Term::If(t1, t2, t3) => Expression::IfExpr(
+            Box::new(restore(t1, names, free)),
+            Box::new(restore(t2, names, free)),
+            Box::new(restore(t3, names, free))
+        ),
+        Term::Uop(op, t1) => Expression::UopExpr(op.clone(), Box::new(restore(t1, names, free))),
+        Term::Bop(op, t1, t2) => Expression::BopExpr(op.clone(), Box::new(restore(t1, names, free)), Box::new(restore(t2, names, free))),
+        Term::Error => Expression::ErrorExpr
+    }
+}
+
+// Whether index k, counted from outside the term, occurs in term
+fn occurs(t: &Term, k: usize) -> bool {
+    match t {
+        Term::Var(i) => *i == k,
+        Term::Lam(t1) => occurs(t1, k + 1),
+        Term::Let(t1, t2) => occurs(t1, k) || occurs(t2, k + 1),
+        Term::App(t1, t2) | Term::Bop(_, t1, t2) => occurs(t1, k) || occurs(t2, k),
+        Term::If(t1, t2, t3) => occurs(t1, k) || occurs(t2, k) || occurs(t3, k),
+        Term::Uop(_, t1) | Term::Proj(t1, _) => occurs(t1, k),
+        Term::Fix(tlist, _) | Term::Tuple(tlist) | Term::List(tlist) | Term::Construct(_, _, tlist) => tlist.iter().any(|t1| occurs(t1, k)),
+        Term::Match(t1, tcases) => occurs(t1, k) || tcases.iter().any(|(_, nvars, t2)| occurs(t2, k + nvars)),
+        _ => false
+    }
+}
+
+// Decrement indices at or above cutoff, after removing an unused binder
+fn unshift(t: &Term, cutoff: usize) -> Term {
+    let go = |t1: &Term, c: usize| Box::new(unshift(t1, c));
+    match t {
+        Term::Var(i) if *i >= cutoff => Term::Var(i - 1),
+        Term::Lam(t1) => Term::Lam(go(t1, cutoff + 1)),
+        Term::Let(t1, t2) => Term::Let(go(t1, cutoff), go(t2, cutoff + 1)),
+        Term::App(t1, t2) => Term::App(go(t1, cutoff), go(t2, cutoff)),
+        Term::If(t1, t2, t3) => Term::If(go(t1, cutoff), go(t2, cutoff), go(t3, cutoff)),
+        Term::Uop(op, t1) => Term::Uop(op.clone(), go(t1, cutoff)),
+        Term::Bop(op, t1, t2) => Term::Bop(op.clone(), go(t1, cutoff), go(t2, cutoff)),
+        Term::Fix(tlist, i) => Term::Fix(tlist.iter().map(|t1| unshift(t1, cutoff)).collect(), *i),
+        Term::Tuple(tlist) => Term::Tuple(tlist.iter().map(|t1| unshift(t1, cutoff)).collect()),
+        Term::List(tlist) => Term::List(tlist.iter().map(|t1| unshift(t1, cutoff)).collect()),
+        Term::Proj(t1, i) => Term::Proj(go(t1, cutoff), *i),
+        Term::Construct(d, i, tlist) => Term::Construct(d.clone(), *i, tlist.iter().map(|t1| unshift(t1, cutoff)).collect()),
+        Term::Match(t1, tcases) => Term::Match(
+            go(t1, cutoff),
+            tcases.iter().map(|(c, nvars, t2)| (c.clone(), *nvars, unshift(t2, cutoff + nvars))).collect()
+        ),
+        _ => t.clone()
+    }
+}
+
+// Eta reduce everywhere, bottom up, so \x. f x becomes f when x does not occur in f
+pub fn eta_normal(t: &Term) -> Term {
+    let go = |t1: &Term| Box::new(eta_normal(t1));
+    match t {
+        Term::Lam(t1) => match eta_normal(t1) {
+            Term::App(f, x) if *x == Term::Var(0) && !occurs(&f, 0) => unshift(&f, 0),
+            body => Term::Lam(Box::new(body))
+        },
+        Term::Let(t1, t2) => Term::Let(go(t1), go(t2)),
+        Term::App(t1, t2) => Term::App(go(t1), go(t2)),
+        Term::If(t1, t2, t3) => Term::If(go(t1), go(t2), go(t3)),
+        Term::Uop(op, t1) => Term::Uop(op.clone(), go(t1)),
+        Term::Bop(op, t1, t2) => Term::Bop(op.clone(), go(t1), go(t2)),
+        Term::Fix(tlist, i) => Term::Fix(tlist.iter().map(eta_normal).collect(), *i),
+        Term::Tuple(tlist) => Term::Tuple(tlist.iter().map(eta_normal).collect()),
+        Term::List(tlist) => Term::List(tlist.iter().map(eta_normal).collect()),
+        Term::Proj(t1, i) => Term::Proj(go(t1), *i),
+        Term::Construct(d, i, tlist) => Term::Construct(d.clone(), *i, tlist.iter().map(eta_normal).collect()),
+        Term::Match(t1, tcases) => Term::Match(go(t1), tcases.iter().map(|(c, nvars, t2)| (c.clone(), *nvars, eta_normal(t2))).collect()),
+        _ => t.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{evaluator::nest, lexer::Lexer, parser::Parser, printing::{debruijn_to_string, grouped_expression_to_string}};
+
+    fn term(src: &str) -> Term {
+        let tokens = Lexer::new().generate(src).unwrap();
+        to_debruijn(&nest(Parser::new(false, false).parse_program(tokens).unwrap()))
+    }
+
+    #[test]
+    fn indices_count_binders_to_the_innermost() {
+        assert_eq!(debruijn_to_string(&term(r"\x y. x y z"), 0), r"(\. \. 1 0 z)");
+        assert_eq!(debruijn_to_string(&term(r"\x y. x y z"), 1), r"(\. \. 2 1 z)");
+        assert_eq!(debruijn_to_string(&term(r"\x. let y = x in \z. y z"), 0), r"(\. (let 0 in (\. 1 0)))");
+    }
+
+    #[test]
+    fn renamed_binders_give_the_same_term() {
+        assert_eq!(term(r"\x. \y. x"), term(r"\a. \b. a"));
+        assert_ne!(term(r"\x. \y. x"), term(r"\x. \y. y"));
+        // Shadowing refers to the innermost binder
+        assert_eq!(term(r"\x. \x. x"), term(r"\a. \b. b"));
+    }
+
+    #[test]
+    fn tuple_patterns_bind_their_components_by_projection() {
+        assert_eq!(debruijn_to_string(&term(r"\(a, b). b"), 0), r"(\. (let 0.0 in (let 1.1 in 0)))");
+    }
+
+    #[test]
+    fn restored_names_avoid_free_variables() {
+        let e = from_debruijn(&term(r"\x y. y x0"));
+        assert_eq!(grouped_expression_to_string(&e, false), r"(\x0' x1. x1 x0)");
+        assert_eq!(to_debruijn(&e), term(r"\x y. y x0"));
+    }
+
+    #[test]
+    fn restoring_keeps_lets_matches_and_unbound_indices() {
+        for src in [r"\f. let g = f 1 in g (f 2)", r"(type t = A | B int in \x. match x with A -> 0 | B n -> n)"] {
+            assert_eq!(to_debruijn(&from_debruijn(&term(src))), term(src));
+        }
+        assert_eq!(grouped_expression_to_string(&from_debruijn(&Term::Var(2)), false), "#2");
+    }
+}
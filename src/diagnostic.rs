@@ -0,0 +1,123 @@
+use std::fmt::Write;
+use crate::error::Error;
+
+// ANSI escape codes
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+// Wrap text in an escape code if color is enabled
+fn paint(text: &str, style: &str, color: bool) -> String {
+    if color { style.to_string() + text + RESET } else { text.to_string() }
+}
+
+// Render an error with a header, the offending source line, and a caret underline
+pub fn render(err: &Error, source: &str, fname: &str, color: bool) -> String {
+    let ((row, col), (end_row, end_col)) = err.span();
+    // Offending line, empty if error is past the last line
+    let line = source.lines().nth(row).unwrap_or("");
+    let line_len = line.chars().count();
+    // Underline at least one character, clamp multi-line spans to end of line
+    let len = if end_row > row { line_len.saturating_sub(col) } else { end_col.saturating_sub(col) };
+    let len = len.max(1);
+    // Indent caret, keeping tabs so it lines up with the source
+    let mut indent: String = line.chars().take(col).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+    indent.push_str(&" ".repeat(col.saturating_sub(line_len)));
+    // Gutter is as wide as the line number
+    let num = (row + 1).to_string();
+    let pad = " ".repeat(num.len());
+    let bar = paint("|", BLUE, color);
+    // Write lines, writing into a String cannot fail
+    let mut s = String::new();
+    writeln!(s, "{}{}", paint("error", RED, color), paint(&(": ".to_string() + &err.message()), BOLD, color)).unwrap();
+    writeln!(s, "{}{} {}:{}:{}", pad, paint("-->", BLUE, color), fname, row + 1, col + 1).unwrap();
+    writeln!(s, "{} {}", pad, bar).unwrap();
+    writeln!(s, "{} {} {}", paint(&num, BLUE, color), bar, line).unwrap();
+    writeln!(s, "{} {} {}{}", pad, bar, indent, paint(&("^".repeat(len) + " " + &err.label()), RED, color)).unwrap();
+    s
+}
+
+// Escape string for a JSON string literal
+fn json_str(x: &str) -> String {
+    let mut s = String::from("\"");
+    for c in x.chars() {
+        match c {
+            '"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            '\r' => s.push_str("\\r"),
+            '\t' => s.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(s, "\\u{:04x}", c as u32).unwrap(),
+            c => s.push(c)
+        }
+    }
+    s.push('"');
+    s
+}
+
+// Render an error as a single-line JSON object for editor integration
+pub fn render_json(err: &Error, fname: &str) -> String {
+    let ((row, col), (end_row, end_col)) = err.span();
+    format!(
+        "{{\"file\":{},\"severity\":\"error\",\"kind\":{},\"message\":{},\"label\":{},\"start\":{{\"line\":{},\"column\":{}}},\"end\":{{\"line\":{},\"column\":{}}}}}",
+        json_str(fname),
+        json_str(err.kind()),
+        json_str(&err.message()),
+        json_str(&err.label()),
+        row + 1, col + 1,
+        end_row + 1, end_col + 1
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unbound(span: ((usize, usize), (usize, usize))) -> Error {
+        Error::UnboundError("y".to_string(), span)
+    }
+
+    #[test]
+    fn renders_header_source_line_and_caret() {
+        let s = render(&unbound(((1, 4), (1, 5))), "let x = 1 in\nx + y", "f.lc", false);
+        let lines: Vec<&str> = s.lines().collect();
+        assert_eq!(lines, vec![
+            "error: Unbound variable 'y'",
+            " --> f.lc:2:5",
+            "  |",
+            "2 | x + y",
+            "  |     ^ 'y' is not bound to a value"
+        ]);
+    }
+
+    #[test]
+    fn caret_keeps_tabs_and_underlines_whole_span() {
+        let s = render(&unbound(((0, 1), (0, 4))), "\tabc d", "f.lc", false);
+        assert_eq!(s.lines().last().unwrap(), "  | \t^^^ 'y' is not bound to a value");
+    }
+
+    #[test]
+    fn error_past_the_last_line_still_renders() {
+        let s = render(&unbound(((3, 2), (3, 2))), "x", "f.lc", false);
+        assert!(s.contains(" --> f.lc:4:3"));
+        assert!(s.lines().last().unwrap().ends_with("  ^ 'y' is not bound to a value"));
+    }
+
+    #[test]
+    fn color_wraps_parts_in_escape_codes() {
+        let s = render(&unbound(((0, 0), (0, 1))), "y", "f.lc", true);
+        assert!(s.starts_with(&(RED.to_string() + "error" + RESET)));
+        assert!(!render(&unbound(((0, 0), (0, 1))), "y", "f.lc", false).contains('\x1b'));
+    }
+
+    #[test]
+    fn json_has_one_based_positions_and_escaped_strings() {
+        let e = Error::LexError('"', ((0, 9), (0, 10)));
+        assert_eq!(
+            render_json(&e, "a\\b.lc"),
+            r#"{"file":"a\\b.lc","severity":"error","kind":"lex","message":"Unexpected character '\\\"'","label":"unexpected character","start":{"line":1,"column":10},"end":{"line":1,"column":11}}"#
+        );
+        assert_eq!(json_str("\u{1}\n"), r#""\u0001\n""#);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::error::Error;
+use crate::evaluator::{self, Evaluator};
+use crate::debruijn::{to_debruijn, eta_normal};
+use crate::types::ast::{Expression, Program};
+
+// Result of comparing two programs
+pub struct Equivalence {
+    // Equal up to renaming of bound variables
+    pub alpha: bool,
+    // Equal normal forms up to renaming and eta, unknown if either has no normal form within the budget
+    pub beta_eta: Option<bool>,
+    // Normal form of each program, if reached
+    pub normal: (Option<Expression>, Option<Expression>)
+}
+
+// Reduce program to normal form, or nothing if the step budget runs out
+pub fn normal_form(prog: Program, max_steps: usize) -> Result<Option<Expression>, Error> {
+    let mut evaluate = Evaluator::new();
+    evaluate.set_max_steps(Some(max_steps));
+    match evaluate.normalize_program(prog) {
+        Ok(e) => Ok(Some(e)),
+        Err(Error::StepLimitError(..)) => Ok(None),
+        Err(e) => Err(e)
+    }
+}
+
+// Compare two programs given their normal forms
+pub fn compare(a: &Expression, b: &Expression, na: Option<Expression>, nb: Option<Expression>) -> Equivalence {
+    let alpha = to_debruijn(a) == to_debruijn(b);
+    // Compare nameless, eta reduced normal forms
+    let beta_eta = match (&na, &nb) {
+        (Some(x), Some(y)) => Some(alpha || eta_normal(&to_debruijn(x)) == eta_normal(&to_debruijn(y))),
+        _ => None
+    };
+    Equivalence { alpha, beta_eta, normal: (na, nb) }
+}
+
+// Compare two programs, normalising each within the step budget
+pub fn equivalent(a: Program, b: Program, max_steps: usize) -> Result<Equivalence, Error> {
+    let (ea, eb) = (evaluator::nest(a.clone()), evaluator::nest(b.clone()));
+    let na = normal_form(a, max_steps)?;
+    let nb = normal_form(b, max_steps)?;
+    Ok(compare(&ea, &eb, na, nb))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn equiv(a: &str, b: &str, max_steps: usize) -> Equivalence {
+        let parse = |src: &str| Parser::new(false, false).parse_program(Lexer::new().generate(src).unwrap()).unwrap();
+        equivalent(parse(a), parse(b), max_steps).unwrap()
+    }
+
+    #[test]
+    fn renaming_is_alpha_equivalence() {
+        let r = equiv(r"\x y. x", r"\a b. a", 100);
+        assert!(r.alpha);
+        assert_eq!(r.beta_eta, Some(true));
+    }
+
+    #[test]
+    fn reduction_and_eta_are_beta_eta_equivalence() {
+        let r = equiv(r"(\x. x) (\y. y)", r"\z. z", 100);
+        assert_eq!((r.alpha, r.beta_eta), (false, Some(true)));
+        assert_eq!(equiv(r"\x. f x", "f", 100).beta_eta, Some(true));
+        assert_eq!(equiv(r"\x y. x", r"\x y. y", 100).beta_eta, Some(false));
+    }
+
+    #[test]
+    fn statements_are_part_of_the_program() {
+        assert_eq!(equiv(r"let id = \x. x in id id", r"\y. y", 100).beta_eta, Some(true));
+    }
+
+    #[test]
+    fn unknown_without_a_normal_form_within_budget() {
+        let omega = r"(\x. x x) (\x. x x)";
+        let r = equiv(omega, omega, 50);
+        assert!(r.alpha);
+        assert_eq!(r.beta_eta, None);
+        assert!(r.normal.0.is_none() && r.normal.1.is_none());
+    }
+}
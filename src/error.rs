@@ -0,0 +1,269 @@
+use std::fmt;
+use std::time::Duration;
+use crate::types::{ast::{Expression, Type}, token::{Span, Variant}};
+use crate::printing::{grouped_expression_to_string, type_to_string, types_to_strings};
+
+// Errors produced by the lexer, parser, and evaluator
+#[derive(Clone, Debug)]
+pub enum Error {
+    // Unexpected character
+    LexError(char, Span),
+    // Block comment without closing delimiter
+    CommentError(Span),
+    // Expected token variants, found token variant, where the tokens were expected
+    SyntaxError(Vec<Variant>, Variant, Option<&'static str>, Span),
+    // Operator name, operand types
+    TypeError(String, Vec<String>, Span),
+    // Parameter count, argument count
+    ArityError(usize, usize, Span),
+    // Variable name
+    UnboundError(String, Span),
+    // Steps taken, partially reduced term
+    StepLimitError(usize, Box<Expression>, Span),
+    // Time allowed, partially reduced term
+    TimeoutError(Duration, Box<Expression>, Span),
+    // Nesting depth allowed, partially reduced term
+    DepthLimitError(usize, Box<Expression>, Span),
+    // Type name in an annotation
+    UnknownTypeError(String, Span),
+    // Expected type, found type
+    MismatchError(Type, Type, Span),
+    // Type variable, type containing it
+    InfiniteTypeError(Type, Type, Span),
+    // Parameter without the annotation an explicitly typed function needs
+    AnnotationError(String, Span),
+    // Kind of type expected, type found
+    ExpectedTypeError(&'static str, Type, Span),
+    // Operator name
+    EmptyListError(&'static str, Span),
+    // Value no case matched
+    MatchError(Box<Expression>, Span),
+}
+
+// Variants that can start an expression, reported together as "expression"
+const EXPRESSION_START: [Variant; 21] = [
+    Variant::Lambda,
+    Variant::Let,
+    Variant::If,
+    Variant::Match,
+    Variant::Type,
+    Variant::LParen,
+    Variant::Ident,
+    Variant::Number,
+    Variant::Boolean,
+    Variant::Minus,
+    Variant::Not,
+    Variant::Fix,
+    Variant::Fst,
+    Variant::Snd,
+    Variant::LBracket,
+    Variant::Cons,
+    Variant::Nil,
+    Variant::Head,
+    Variant::Tail,
+    Variant::IsNil,
+    Variant::Unit,
+];
+
+// Describe a token variant for error messages
+pub fn variant_to_str(x: &Variant) -> &'static str {
+    match x {
+        Variant::Lambda => "`\\`",
+        Variant::Dot => "`.`",
+        Variant::LParen => "`(`",
+        Variant::RParen => "`)`",
+        Variant::Ident => "identifier",
+        Variant::EOF => "end of input",
+        Variant::Number => "number",
+        Variant::Boolean => "boolean",
+        Variant::Plus => "`+`",
+        Variant::Minus => "`-`",
+        Variant::Times => "`*`",
+        Variant::Div => "`/`",
+        Variant::Gt => "`>`",
+        Variant::Gte => "`>=`",
+        Variant::Lt => "`<`",
+        Variant::Lte => "`<=`",
+        Variant::Eq => "`=`",
+        Variant::Not => "`!`",
+        Variant::And => "`&`",
+        Variant::Or => "`|`",
+        Variant::Xor => "`^`",
+        Variant::Let => "`let`",
+        Variant::In => "`in`",
+        Variant::If => "`if`",
+        Variant::Then => "`then`",
+        Variant::Else => "`else`",
+        Variant::Rec => "`rec`",
+        Variant::RecAnd => "`and`",
+        Variant::Fix => "`fix`",
+        Variant::Colon => "`:`",
+        Variant::Arrow => "`->`",
+        Variant::TypeLambda => "`/\\`",
+        Variant::LBracket => "`[`",
+        Variant::RBracket => "`]`",
+        Variant::Comma => "`,`",
+        Variant::Fst => "`fst`",
+        Variant::Snd => "`snd`",
+        Variant::DoubleColon => "`::`",
+        Variant::Cons => "`cons`",
+        Variant::Nil => "`nil`",
+        Variant::Head => "`head`",
+        Variant::Tail => "`tail`",
+        Variant::IsNil => "`isnil`",
+        Variant::Type => "`type`",
+        Variant::Match => "`match`",
+        Variant::With => "`with`",
+        Variant::Unit => "`_`",
+    }
+}
+
+impl Error {
+    // Source span the error refers to
+    pub fn span(&self) -> Span {
+        match self {
+            Error::LexError(_, s) => *s,
+            Error::CommentError(s) => *s,
+            Error::SyntaxError(_, _, _, s) => *s,
+            Error::TypeError(_, _, s) => *s,
+            Error::ArityError(_, _, s) => *s,
+            Error::UnboundError(_, s) => *s,
+            Error::StepLimitError(_, _, s) => *s,
+            Error::TimeoutError(_, _, s) => *s,
+            Error::DepthLimitError(_, _, s) => *s,
+            Error::UnknownTypeError(_, s) => *s,
+            Error::MismatchError(_, _, s) => *s,
+            Error::InfiniteTypeError(_, _, s) => *s,
+            Error::AnnotationError(_, s) => *s,
+            Error::ExpectedTypeError(_, _, s) => *s,
+            Error::EmptyListError(_, s) => *s,
+            Error::MatchError(_, s) => *s,
+        }
+    }
+    // Short name of the error kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::LexError(_, _) => "lex",
+            Error::CommentError(_) => "lex",
+            Error::SyntaxError(_, _, _, _) => "syntax",
+            Error::TypeError(_, _, _) => "type",
+            Error::ArityError(_, _, _) => "arity",
+            Error::UnboundError(_, _) => "unbound",
+            Error::StepLimitError(_, _, _) => "step-limit",
+            Error::TimeoutError(_, _, _) => "timeout",
+            Error::DepthLimitError(_, _, _) => "depth-limit",
+            Error::UnknownTypeError(_, _) => "syntax",
+            Error::MismatchError(_, _, _) => "type",
+            Error::InfiniteTypeError(_, _, _) => "type",
+            Error::AnnotationError(_, _) => "type",
+            Error::ExpectedTypeError(_, _, _) => "type",
+            Error::EmptyListError(_, _) => "empty-list",
+            Error::MatchError(_, _) => "match",
+        }
+    }
+    // Error message without position
+    pub fn message(&self) -> String {
+        match self {
+            Error::LexError(c, _) => format!("Unexpected character '{}'", c.escape_default()),
+            Error::CommentError(_) => "Unterminated block comment".to_string(),
+            Error::SyntaxError(_, found, _, _) => format!("{}; found {}", self.label(), variant_to_str(found)),
+            Error::TypeError(op, types, _) => {
+                // Quote operand types
+                let types: Vec<String> = types.iter().map(|t| "'".to_string() + t + "'").collect();
+                let plural = if types.len() > 1 { "s" } else { "" };
+                format!("Incompatible type{} {} with operation '{}'", plural, types.join(" and "), op)
+            },
+            Error::ArityError(params, args, _) => {
+                let p_plural = if *params == 1 { "" } else { "s" };
+                let a_plural = if *args == 1 { "" } else { "s" };
+                format!("Function of {} parameter{} applied to {} argument{}", params, p_plural, args, a_plural)
+            },
+            Error::UnboundError(name, _) => format!("Unbound variable '{}'", name),
+            Error::StepLimitError(steps, term, _) => {
+                format!("Step limit exceeded after {} steps; partially reduced term: {}", steps, grouped_expression_to_string(term, false))
+            },
+            Error::TimeoutError(time, term, _) => {
+                format!("Timeout exceeded after {:?}; partially reduced term: {}", time, grouped_expression_to_string(term, false))
+            },
+            Error::DepthLimitError(depth, term, _) => {
+                format!("Depth limit exceeded at {} nested reductions; partially reduced term: {}", depth, grouped_expression_to_string(term, false))
+            },
+            Error::UnknownTypeError(name, _) => format!("Unknown type '{}'", name),
+            Error::MismatchError(expected, found, _) => {
+                let names = types_to_strings(&[expected.clone(), found.clone()]);
+                format!("Type mismatch, expected '{}' but found '{}'", names[0], names[1])
+            },
+            Error::InfiniteTypeError(var, t, _) => {
+                let names = types_to_strings(&[var.clone(), t.clone()]);
+                format!("Cannot construct infinite type '{}' = '{}'", names[0], names[1])
+            },
+            Error::AnnotationError(name, _) => format!("Missing type annotation for parameter '{}'", name),
+            Error::ExpectedTypeError(kind, found, _) => format!("Expected {}, found type '{}'", kind, type_to_string(found)),
+            Error::EmptyListError(op, _) => format!("Operation '{}' applied to an empty list", op),
+            Error::MatchError(value, _) => format!("No matching case for value '{}'", grouped_expression_to_string(value, false)),
+        }
+    }
+    // Short description of the offending span
+    pub fn label(&self) -> String {
+        match self {
+            Error::LexError(_, _) => "unexpected character".to_string(),
+            Error::CommentError(_) => "comment starts here".to_string(),
+            Error::SyntaxError(expected, _, context, _) => {
+                // Collapse variants that start an expression
+                let any_expr = EXPRESSION_START.iter().all(|v| expected.contains(v));
+                // List expected variants
+                let mut list: Vec<&str> = expected.iter()
+                    .filter(|v| !(any_expr && EXPRESSION_START.contains(v)))
+                    .map(variant_to_str)
+                    .collect();
+                if any_expr { list.push("expression") }
+                let expected = match list.len() {
+                    0 => "unexpected token".to_string(),
+                    1 => "expected ".to_string() + list[0],
+                    _ => "expected one of ".to_string() + list.join(", ").as_ref()
+                };
+                // Append context
+                match context {
+                    Some(c) => expected + " " + c,
+                    None => expected
+                }
+            },
+            Error::TypeError(op, types, _) => {
+                let types: Vec<String> = types.iter().map(|t| "'".to_string() + t + "'").collect();
+                format!("'{}' applied to {}", op, types.join(" and "))
+            },
+            Error::ArityError(params, _, _) => {
+                let plural = if *params == 1 { "" } else { "s" };
+                format!("expects at least {} argument{}", params, plural)
+            },
+            Error::UnboundError(name, _) => format!("'{}' is not bound to a value", name),
+            Error::StepLimitError(steps, _, _) => format!("evaluation stopped after {} steps", steps),
+            Error::TimeoutError(time, _, _) => format!("evaluation stopped after {:?}", time),
+            Error::DepthLimitError(depth, _, _) => format!("evaluation stopped at nesting depth {}", depth),
+            Error::UnknownTypeError(_, _) => "not a base type, a declared type with its arguments, or a type variable in scope".to_string(),
+            Error::MismatchError(expected, found, _) => {
+                let names = types_to_strings(&[expected.clone(), found.clone()]);
+                format!("expected '{}', found '{}'", names[0], names[1])
+            },
+            Error::InfiniteTypeError(var, t, _) => {
+                let names = types_to_strings(&[var.clone(), t.clone()]);
+                format!("'{}' occurs in '{}'", names[0], names[1])
+            },
+            Error::AnnotationError(_, _) => "parameters of System F functions need a type annotation".to_string(),
+            Error::ExpectedTypeError(kind, found, _) => format!("expected {}, found '{}'", kind, type_to_string(found)),
+            Error::EmptyListError(op, _) => format!("'{}' needs a list with at least one element", op),
+            Error::MatchError(_, _) => "no case matches this value".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Positions are stored from zero but reported from one
+        let (row, col) = self.span().0;
+        let prefix = match self { Error::SyntaxError(_, _, _, _) => "Syntax error: ", _ => "" };
+        write!(f, "{}{} at {}:{}", prefix, self.message(), row + 1, col + 1)
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,156 @@
+use std::collections::HashSet;
+use crate::substitution::{free_vars, fresh, unspan};
+use crate::types::ast::*;
+
+// Remove trailing parameters of a function that are only passed on as trailing arguments, \x y. f x y becomes f
+pub fn eta_step(params: &[Pattern], types: &[Option<Type>], body: &Expression) -> Option<Expression> {
+    let alist = match unspan(body) {
+        Expression::ApplicationExpr(alist) => alist,
+        _ => return None
+    };
+    // Parameters and arguments kept
+    let mut n = params.len();
+    let mut m = alist.len();
+    while n > 0 && m > 1 {
+        // Only a variable parameter can be passed on
+        let p = match &params[n - 1] {
+            Pattern::Var(Some(p)) => p,
+            _ => break
+        };
+        // Trailing argument must be the parameter itself
+        match unspan(&alist[m - 1]) {
+            Expression::ValExpr(Value::Identifier(y)) if y == p => (),
+            _ => break
+        }
+        // Parameter must not be used by the rest of the chain
+        if alist[..m - 1].iter().any(|ex| free_vars(ex).contains(p)) { break }
+        n -= 1;
+        m -= 1;
+    }
+    if n == params.len() { return None }
+    // Remaining chain, a single expression if only the head is left
+    let body = if m == 1 { alist[0].clone() } else { Expression::ApplicationExpr(alist[..m].to_vec()) };
+    Some(if n == 0 { body } else { Expression::FuncExpr(params[..n].to_vec(), types[..n].to_vec(), Box::new(body)) })
+}
+
+// Eta reduce everywhere, bottom up
+pub fn eta_reduce(e: &Expression) -> Expression {
+    let go = |ex: &Expression| Box::new(eta_reduce(ex));
+    match e {
+        Expression::FuncExpr(params, types, body) => {
+            let mut result = Expression::FuncExpr(params.clone(), types.clone(), go(body.as_ref()));
+            // Reduce until no trailing parameter can be removed
+            while let Expression::FuncExpr(params, types, body) = &result {
+                match eta_step(params, types, body.as_ref()) {
+                    Some(ex) => result = ex,
+                    None => break
+                }
+            }
+            result
+        },
+        Expression::UopExpr(op, e1) => Expression::UopExpr(op.clone(), go(e1.as_ref())),
+        Expression::BopExpr(op, e1, e2) => Expression::BopExpr(op.clone(), go(e1.as_ref()), go(e2.as_ref())),
+        Expression::ApplicationExpr(alist) => Expression::ApplicationExpr(alist.iter().map(eta_reduce).collect()),
+        Expression::IfExpr(c, t, f) => Expression::IfExpr(go(c.as_ref()), go(t.as_ref()), go(f.as_ref())),
+        Expression::FixExpr(flist, i) => Expression::FixExpr(flist.iter().map(eta_reduce).collect(), *i),
+        Expression::LetExpr(id, e1, body) => Expression::LetExpr(id.clone(), go(e1.as_ref()), go(body.as_ref())),
+        Expression::SpanExpr(span, ex) => Expression::SpanExpr(*span, go(ex.as_ref())),
+        Expression::TupleExpr(elist) => Expression::TupleExpr(elist.iter().map(eta_reduce).collect()),
+        Expression::ListExpr(elist) => Expression::ListExpr(elist.iter().map(eta_reduce).collect()),
+        Expression::ProjExpr(ex, i, n) => Expression::ProjExpr(go(ex.as_ref()), *i, *n),
+        Expression::ConstructExpr(d, i, elist) => Expression::ConstructExpr(d.clone(), *i, elist.iter().map(eta_reduce).collect()),
+        Expression::MatchExpr(ex, cases) => Expression::MatchExpr(
+            go(ex.as_ref()),
+            cases.iter().map(|case| Case { constructor: case.constructor.clone(), vars: case.vars.clone(), body: eta_reduce(&case.body) }).collect()
+        ),
+        Expression::TypeFuncExpr(a, ex) => Expression::TypeFuncExpr(a.clone(), go(ex.as_ref())),
+        Expression::TypeAppExpr(ex, t) => Expression::TypeAppExpr(go(ex.as_ref()), t.clone()),
+        Expression::DeclExpr(d, ex) => Expression::DeclExpr(d.clone(), go(ex.as_ref())),
+        // Values, errors, and environment machine nodes are left alone
+        _ => e.clone()
+    }
+}
+
+// Variable name not in avoid
+fn new_name(avoid: &HashSet<String>) -> String {
+    if avoid.contains("x") { fresh("x", avoid) } else { "x".to_string() }
+}
+
+// Eta expand at the top, e becomes \x. e x, and a function gets one more parameter
+pub fn eta_expand(e: &Expression) -> Expression {
+    let mut avoid = free_vars(e);
+    match unspan(e) {
+        Expression::FuncExpr(params, types, body) => {
+            // New parameter must not capture variables of the body
+            avoid.extend(params.iter().flat_map(Pattern::vars).cloned());
+            let x = new_name(&avoid);
+            let mut params = params.clone();
+            params.push(Pattern::Var(Some(x.clone())));
+            let mut types = types.clone();
+            types.push(None);
+            Expression::FuncExpr(params, types, Box::new(apply(body.as_ref(), x)))
+        },
+        _ => {
+            let x = new_name(&avoid);
+            Expression::FuncExpr(vec![Pattern::Var(Some(x.clone()))], vec![None], Box::new(apply(e, x)))
+        }
+    }
+}
+
+// Apply expression to variable, extending an application chain
+fn apply(e: &Expression, x: String) -> Expression {
+    let arg = Expression::ValExpr(Value::Identifier(x));
+    match unspan(e) {
+        Expression::ApplicationExpr(alist) => {
+            let mut alist = alist.clone();
+            alist.push(arg);
+            Expression::ApplicationExpr(alist)
+        },
+        _ => Expression::ApplicationExpr(vec![e.clone(), arg])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{evaluator::nest, lexer::Lexer, parser::Parser, printing::grouped_expression_to_string};
+
+    fn parse(src: &str) -> Expression {
+        let tokens = Lexer::new().generate(src).unwrap();
+        nest(Parser::new(false, false).parse_program(tokens).unwrap())
+    }
+
+    fn reduce(src: &str) -> String {
+        grouped_expression_to_string(&eta_reduce(&parse(src)), false)
+    }
+
+    fn expand(src: &str) -> String {
+        grouped_expression_to_string(&eta_expand(&parse(src)), false)
+    }
+
+    #[test]
+    fn trailing_parameters_passed_on_are_removed() {
+        assert_eq!(reduce(r"\x y. f x y"), "f");
+        assert_eq!(reduce(r"\x y. f y x"), r"(\x y. f y x)");
+        assert_eq!(reduce(r"\x y. f 1 y"), r"(\x. f 1)");
+    }
+
+    #[test]
+    fn parameter_used_elsewhere_is_kept() {
+        assert_eq!(reduce(r"\x. x x"), r"(\x. x x)");
+        assert_eq!(reduce(r"\x. f x x"), r"(\x. f x x)");
+        assert_eq!(reduce(r"\(a, b). f (a, b)"), r"(\(a, b). f (a, b))");
+    }
+
+    #[test]
+    fn reduces_inside_other_expressions() {
+        assert_eq!(reduce(r"(\y. g (\x. h x) y, 1)"), "(g h, 1)");
+    }
+
+    #[test]
+    fn expansion_adds_a_parameter_not_free_in_the_body() {
+        assert_eq!(expand("f"), r"(\x. f x)");
+        assert_eq!(expand(r"\y. x y"), r"(\y x'. x y x')");
+        assert_eq!(reduce(&expand("f")), "f");
+    }
+}
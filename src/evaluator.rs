@@ -1,4 +1,13 @@
-use crate::types::{ast::*, eval::EnvBody};
+use std::{cell::RefCell, rc::Rc};
+use std::time::{Duration, Instant};
+use crate::types::{ast::*, eval::EnvBody, token::Span};
+use crate::error::Error;
+use crate::substitution::{free_vars, fresh, substitute, substitute_renaming, unspan};
+use crate::eta::eta_step;
+use crate::parser::declared;
+
+// Default nesting depth at which evaluation stops, unoptimised builds need up to 20 KiB of stack per level
+pub const MAX_DEPTH: usize = 20_000;
 
 macro_rules! vtype {
     ($e:expr) => {
@@ -7,90 +16,484 @@ macro_rules! vtype {
             Value::Identifier(_) => "keyword",
             Value::Boolean(_) => "bool",
             Value::Closure(_,_,_) => "closure",
+            Value::Tuple(_) => "tuple",
+            Value::List(_) => "list",
+            Value::Data(d, _, _) => d.name.as_str(),
             Value::Unit => "unit"
         }
     }
 }
 
+macro_rules! etype {
+    ($e:expr) => {
+        match $e {
+            Expression::ValExpr(v) => vtype!(v),
+            Expression::FuncExpr(_, _, _) => "closure",
+            Expression::TupleExpr(_) => "tuple",
+            Expression::ListExpr(_) => "list",
+            Expression::ConstructExpr(d, _, _) => d.name.as_str(),
+            _ => "expr"
+        }.to_string()
+    }
+}
+
 macro_rules! value {
-    ($e:expr,$p:path,$l:literal) => {
+    // Operand types are only evaluated when reporting an error
+    ($self:ident, $e:expr, $p:path, $l:literal, $types:expr) => {
         match $e {
             Expression::ValExpr(v) => match v {
                 $p(x) => x.clone(),
-                _ => return Err("Incompatible type '".to_string() + vtype!(v) + concat!("' with operation '", $l, "'"))
+                Value::Identifier(id) => return Err(Error::UnboundError(id.clone(), $self.span)),
+                _ => return Err(Error::TypeError($l.to_string(), $types, $self.span))
             },
-            _ => return Err(concat!("Incompatible type 'expr' with operation '", $l, "'").to_string())
+            _ => return Err(Error::TypeError($l.to_string(), $types, $self.span))
         }
     }
 }
 
 macro_rules! bop {
     // Use if input types == output type
-    ($e1: ident, $e2: ident, $e3: ident, $val: path, $op: tt, $s: literal) => {
+    ($self: ident, $e1: ident, $e2: ident, $val: path, $op: tt, $s: literal) => {
         {
             // Get values from e1 and e2
-            let v1 = value!($e1.as_ref(), $val, $s);
-            let v2 = value!($e2.as_ref(), $val, $s);
-            // Update expr
-            *$e3 = Expression::ValExpr($val(v1 $op v2));
-            // Return true
-            Ok(true)
+            let v1 = value!($self, $e1, $val, $s, vec![etype!($e1), etype!($e2)]);
+            let v2 = value!($self, $e2, $val, $s, vec![etype!($e1), etype!($e2)]);
+            // Return result
+            Ok(Expression::ValExpr($val(v1 $op v2)))
         }
     };
     // Use if input types != output type
-    ($e1: ident, $e2: ident, $e3: ident, $val1: path, $val2: path, $op: tt, $s: literal) => {
+    ($self: ident, $e1: ident, $e2: ident, $val1: path, $val2: path, $op: tt, $s: literal) => {
         {
             // Get values from e1 and e2
-            let v1 = value!($e1.as_ref(), $val1, $s);
-            let v2 = value!($e2.as_ref(), $val1, $s);
-            // Update expr
-            *$e3 = Expression::ValExpr($val2(v1 $op v2));
-            // Return true
-            Ok(true)
+            let v1 = value!($self, $e1, $val1, $s, vec![etype!($e1), etype!($e2)]);
+            let v2 = value!($self, $e2, $val1, $s, vec![etype!($e1), etype!($e2)]);
+            // Return result
+            Ok(Expression::ValExpr($val2(v1 $op v2)))
         }
     }
 }
 
-#[derive(Clone)]
+// Shared so that delayed arguments can capture environments without copying them
+#[derive(Clone, Debug)]
 pub struct Environment {
-    data: EnvBody
+    data: Rc<EnvBody>
+}
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 impl Environment {
     pub fn new() -> Environment {
         Environment {
-            data: Vec::new()
+            data: Rc::new(Vec::new())
         }
     }
     pub fn data(&self) -> &EnvBody {
         self.data.as_ref()
     }
-    pub fn push(&mut self, ident: &String, item: Expression) {
-        self.data.push((ident.clone(), item))
+    pub fn push(&mut self, ident: &str, item: Expression) {
+        Rc::make_mut(&mut self.data).push((ident.to_string(), item))
     }
     pub fn clear(&mut self) {
-        self.data.clear()
+        self.data = Rc::new(Vec::new())
     }
     pub fn read(&self, ident: &String) -> Option<Expression> {
         // Iterate backwards through vector, return first match
-        match self.data.iter().rev().find(|r| r.0 == *ident) {
-            Some((_,ex)) => Some(ex.clone()),
-            None => None
+        self.data.iter().rev().find(|r| r.0 == *ident).map(|(_, ex)| ex.clone())
+    }
+}
+
+// Reduction rules, reported when tracing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rule {
+    Lookup,
+    Delta,
+    Closure,
+    Beta,
+    If,
+    Let,
+    Fix,
+    Match,
+    Eta
+}
+impl Rule {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::Lookup => "lookup",
+            Rule::Delta => "delta",
+            Rule::Closure => "closure",
+            Rule::Beta => "beta",
+            Rule::If => "if",
+            Rule::Let => "let",
+            Rule::Fix => "fix",
+            Rule::Match => "match",
+            Rule::Eta => "eta"
         }
     }
 }
 
+// Order in which arguments are evaluated
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    // Evaluate arguments before applying
+    Value,
+    // Substitute unevaluated arguments, evaluating them each time they are used
+    Name,
+    // Substitute shared thunks, evaluating them at most once
+    Need
+}
+
+// Machine that performs reduction steps
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Engine {
+    // Environments and closures
+    Env,
+    // Substitution into function bodies
+    Subst
+}
+
+// Substitution performed by a step
+#[derive(Clone, Debug)]
+pub struct Substitution {
+    pub var: Pattern,
+    pub value: Expression,
+    // Expression substituted into
+    pub body: Expression,
+    // Binders renamed to avoid capture, old and new name
+    pub renames: Vec<(String, String)>
+}
+
+// Reduction step reported to tracer
+pub struct Step<'a> {
+    // Step number, zero for the initial expression
+    pub number: usize,
+    // Rule that fired, none for the initial expression
+    pub rule: Option<Rule>,
+    pub substitution: Option<Substitution>,
+    // Expression after the step
+    pub expr: &'a Expression
+}
+
+pub type Tracer = Box<dyn FnMut(&Step)>;
+
 pub struct Evaluator {
     env: Environment,
-    dynamic: bool
+    dynamic: bool,
+    span: Span,
+    strategy: Strategy,
+    engine: Engine,
+    normal: bool,
+    eta: bool,
+    rule: Option<Rule>,
+    substitution: Option<Substitution>,
+    steps: usize,
+    tracer: Option<Tracer>,
+    max_steps: Option<usize>,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    depth: usize,
+    max_depth: usize
+}
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 impl Evaluator {
     pub fn new() -> Evaluator {
         Evaluator{  
             env: Environment::new(),
-            dynamic: false
+            dynamic: false,
+            span: ((0, 0), (0, 0)),
+            strategy: Strategy::Value,
+            engine: Engine::Env,
+            normal: false,
+            eta: false,
+            rule: None,
+            substitution: None,
+            steps: 0,
+            tracer: None,
+            max_steps: None,
+            timeout: None,
+            deadline: None,
+            depth: 0,
+            max_depth: MAX_DEPTH
+        }
+    }
+    // Select when arguments are evaluated
+    pub fn set_strategy(&mut self, strategy: Strategy) {
+        self.strategy = strategy
+    }
+    // Select machine used to evaluate programs
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine
+    }
+    // Bind an unevaluated expression according to strategy, evaluated in the current environment
+    fn delay(&self, e: Expression) -> Expression {
+        match self.strategy {
+            Strategy::Value => e,
+            Strategy::Name => Expression::EnvExpr(self.env.clone(), Box::new(e)),
+            Strategy::Need => Expression::ThunkExpr(Rc::new(RefCell::new(Expression::EnvExpr(self.env.clone(), Box::new(e)))))
+        }
+    }
+    // Eta reduce functions when normalising
+    pub fn set_eta(&mut self, eta: bool) {
+        self.eta = eta
+    }
+    // Stop evaluation after a number of reduction steps
+    pub fn set_max_steps(&mut self, max_steps: Option<usize>) {
+        self.max_steps = max_steps
+    }
+    // Stop evaluation after an amount of time
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout
+    }
+    // Stop evaluation when reducing a subterm nested this deep, the stack must have room for it
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth
+    }
+    // Reset limits before evaluating input
+    fn start(&mut self) {
+        self.steps = 0;
+        // Deadline too far away to represent is no deadline
+        self.deadline = self.timeout.and_then(|t| Instant::now().checked_add(t));
+    }
+    // Report each reduction step to tracer
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer
+    }
+    // Perform unary operation on a fully reduced operand
+    fn uop(&mut self, op: &Uop, e1: &Expression) -> Result<Expression, Error> {
+        self.rule = Some(Rule::Delta);
+        match op {
+            Uop::NotUop => {
+                // Get boolean value from e1
+                let bool_val = value!(self, e1, Value::Boolean, "!", vec![etype!(e1)]);
+                // Return result
+                Ok(Expression::ValExpr(Value::Boolean(!bool_val)))
+            },
+            Uop::NegUop => {
+                // Get integer value from e1
+                let int_val = value!(self, e1, Value::Number, "unary -", vec![etype!(e1)]);
+                // Return result
+                Ok(Expression::ValExpr(Value::Number(-int_val)))
+            },
+            Uop::IsNilUop => {
+                let elist = self.elements(e1, "isnil", &[e1])?;
+                Ok(Expression::ValExpr(Value::Boolean(elist.is_empty())))
+            },
+            Uop::HeadUop => match self.elements(e1, "head", &[e1])?.first() {
+                Some(ex) => Ok(ex.clone()),
+                None => Err(Error::EmptyListError("head", self.span))
+            },
+            Uop::TailUop => {
+                let elist = self.elements(e1, "tail", &[e1])?;
+                if elist.is_empty() { return Err(Error::EmptyListError("tail", self.span)) }
+                Ok(rebuild(e1, elist[1..].to_vec()))
+            }
+        }
+    }
+    // Elements of a fully reduced list, operands are reported if it is not one
+    fn elements<'a>(&self, e: &'a Expression, op: &str, operands: &[&Expression]) -> Result<&'a Vec<Expression>, Error> {
+        match e {
+            Expression::ListExpr(elist) | Expression::ValExpr(Value::List(elist)) => Ok(elist),
+            Expression::ValExpr(Value::Identifier(id)) => Err(Error::UnboundError(id.clone(), self.span)),
+            _ => Err(Error::TypeError(op.to_string(), operands.iter().map(|ex| etype!(ex)).collect(), self.span))
+        }
+    }
+    // Perform binary operation on fully reduced operands
+    fn bop(&mut self, op: &Bop, e1: &Expression, e2: &Expression) -> Result<Expression, Error> {
+        self.rule = Some(Rule::Delta);
+        match op {
+            Bop::PlusBop => bop!(self, e1, e2, Value::Number, +, "+"),
+            Bop::MinusBop => bop!(self, e1, e2, Value::Number, -, "-"),
+            Bop::TimesBop => bop!(self, e1, e2, Value::Number, *, "*"),
+            Bop::DivBop => bop!(self, e1, e2, Value::Number, /, "/"),
+            Bop::AndBop => bop!(self, e1, e2, Value::Boolean, &&, "&"),
+            Bop::OrBop => bop!(self, e1, e2, Value::Boolean, ||, "|"),
+            Bop::XorBop => bop!(self, e1, e2, Value::Boolean, ^, "^"),
+            Bop::GtBop => bop!(self, e1, e2, Value::Number, Value::Boolean, >, ">"),
+            Bop::GteBop => bop!(self, e1, e2, Value::Number, Value::Boolean, >=, ">="),
+            Bop::LtBop => bop!(self, e1, e2, Value::Number, Value::Boolean, <, "<"),
+            Bop::LteBop => bop!(self, e1, e2, Value::Number, Value::Boolean, <=, "<="),
+            Bop::EqBop => Ok(Expression::ValExpr(Value::Boolean(self.equal(e1, e2)?))),
+            Bop::ConsBop => {
+                // Prepend to a list of the same kind, only the right operand has to be a list
+                let mut elist = vec![e1.clone()];
+                elist.extend(self.elements(e2, ":: onto a list", &[e2])?.iter().cloned());
+                Ok(rebuild(e2, elist))
+            }
+        }
+    }
+    // Structural equality of fully reduced numbers, booleans, unit, tuples, lists, and data
+    fn equal(&self, e1: &Expression, e2: &Expression) -> Result<bool, Error> {
+        match (unspan(e1), unspan(e2)) {
+            (Expression::ValExpr(Value::Number(x)), Expression::ValExpr(Value::Number(y))) => Ok(x == y),
+            (Expression::ValExpr(Value::Boolean(x)), Expression::ValExpr(Value::Boolean(y))) => Ok(x == y),
+            (Expression::ValExpr(Value::Unit), Expression::ValExpr(Value::Unit)) => Ok(true),
+            (Expression::ValExpr(Value::Identifier(id)), _) | (_, Expression::ValExpr(Value::Identifier(id))) => Err(Error::UnboundError(id.clone(), self.span)),
+            // Lists are equal when they have the same length and equal elements
+            (Expression::ListExpr(l1) | Expression::ValExpr(Value::List(l1)), Expression::ListExpr(l2) | Expression::ValExpr(Value::List(l2))) => {
+                if l1.len() != l2.len() { return Ok(false) }
+                for (c1, c2) in l1.iter().zip(l2.iter()) {
+                    if !self.equal(c1, c2)? { return Ok(false) }
+                }
+                Ok(true)
+            },
+            // Data values are equal when they use the same constructor with equal fields
+            (t1, t2) if constructed(t1).is_some() && constructed(t2).is_some() => {
+                let ((d1, i1, f1), (d2, i2, f2)) = (constructed(t1).unwrap(), constructed(t2).unwrap());
+                if d1.name != d2.name || i1 != i2 { return Ok(false) }
+                for (c1, c2) in f1.iter().zip(f2.iter()) {
+                    if !self.equal(c1, c2)? { return Ok(false) }
+                }
+                Ok(true)
+            },
+            (t1, t2) => match (components(t1), components(t2)) {
+                // Tuples of the same size are equal when all components are
+                (Some(l1), Some(l2)) if l1.len() == l2.len() => {
+                    for (c1, c2) in l1.iter().zip(l2.iter()) {
+                        if !self.equal(c1, c2)? { return Ok(false) }
+                    }
+                    Ok(true)
+                },
+                _ => Err(Error::TypeError("=".to_string(), vec![etype!(t1), etype!(t2)], self.span))
+            }
         }
     }
-    fn step(&mut self, expr: &mut Expression) -> Result<bool, String> {
+    // Select component of a fully reduced tuple, which must have the size the projection expects if it knows one
+    fn project(&mut self, e: &Expression, i: usize, n: Option<usize>) -> Result<Expression, Error> {
+        match components(e) {
+            Some(elist) if n.is_some_and(|n| n != elist.len()) => {
+                Err(Error::TypeError(format!(".{} of a {}-tuple", i, n.unwrap()), vec![format!("{}-tuple", elist.len())], self.span))
+            },
+            Some(elist) if i < elist.len() => {
+                self.rule = Some(Rule::Delta);
+                Ok(elist[i].clone())
+            },
+            _ => match e {
+                Expression::ValExpr(Value::Identifier(id)) => Err(Error::UnboundError(id.clone(), self.span)),
+                _ => Err(Error::TypeError(format!(".{}", i), vec![etype!(e)], self.span))
+            }
+        }
+    }
+    // First case matching a fully reduced value, with the expressions bound to its variables
+    fn select(&mut self, e: &Expression, cases: &[Case]) -> Result<(usize, Vec<Expression>), Error> {
+        let data = constructed(e);
+        for (i, case) in cases.iter().enumerate() {
+            match (&case.constructor, data) {
+                // Constructor case binds the fields
+                (Some((cd, ci)), Some((d, di, fields))) if cd.name == d.name && *ci == di => {
+                    self.rule = Some(Rule::Match);
+                    return Ok((i, fields.clone()))
+                },
+                (Some(_), _) => (),
+                // Variable case binds the whole value
+                (None, _) => {
+                    self.rule = Some(Rule::Match);
+                    return Ok((i, vec![e.clone()]))
+                }
+            }
+        }
+        match e {
+            _ if data.is_some() => Err(Error::MatchError(Box::new(e.clone()), self.span)),
+            Expression::ValExpr(Value::Identifier(id)) => Err(Error::UnboundError(id.clone(), self.span)),
+            _ => Err(Error::TypeError("match".to_string(), vec![etype!(e)], self.span))
+        }
+    }
+    // Variables of a pattern with the expressions bound to them, components of a tuple directly and anything else by projection
+    fn bindings(&self, p: &Pattern, e: Expression) -> Vec<(String, Expression)> {
+        match p {
+            Pattern::Var(Some(x)) => vec![(x.clone(), self.delay(e))],
+            Pattern::Var(None) => Vec::new(),
+            Pattern::Tuple(plist) => match components(unspan(&e)) {
+                Some(elist) if elist.len() == plist.len() => plist.iter().zip(elist.clone()).flat_map(|(q, ex)| self.bindings(q, ex)).collect(),
+                // Projections share the delayed value
+                _ => {
+                    let shared = self.delay(e);
+                    plist.iter().enumerate()
+                        .flat_map(|(i, q)| self.bindings(q, Expression::ProjExpr(Box::new(shared.clone()), i, Some(plist.len()))))
+                        .collect()
+                }
+            }
+        }
+    }
+    // Substitute for the variables of a pattern, components of a tuple directly and anything else by projection
+    fn substitute_pattern(&mut self, p: &Pattern, body: &Expression, v: &Expression) -> Expression {
+        match p {
+            Pattern::Var(Some(x)) => self.substitute(body, x, v),
+            Pattern::Var(None) => body.clone(),
+            Pattern::Tuple(_) => {
+                // Rename variables free in the value first, so substituting one component cannot capture another
+                let fv = free_vars(v);
+                let mut avoid = fv.clone();
+                avoid.extend(free_vars(body));
+                avoid.extend(p.vars().into_iter().cloned());
+                let mut renamed = p.clone();
+                let mut scope = body.clone();
+                let mut renames = Vec::new();
+                for id in renamed.idents_mut() {
+                    let Some(x) = id.clone().filter(|x| fv.contains(x)) else { continue };
+                    let new = fresh(&x, &avoid);
+                    avoid.insert(new.clone());
+                    scope = substitute(&scope, &x, &Expression::ValExpr(Value::Identifier(new.clone())));
+                    renames.push((x, new.clone()));
+                    *id = Some(new);
+                }
+                let Pattern::Tuple(plist) = renamed else { return scope };
+                let parts: Vec<Expression> = match components(unspan(v)) {
+                    Some(elist) if elist.len() == plist.len() => elist.clone(),
+                    _ => (0..plist.len()).map(|i| Expression::ProjExpr(Box::new(v.clone()), i, Some(plist.len()))).collect()
+                };
+                let result = plist.iter().zip(parts).fold(scope, |acc, (q, ex)| {
+                    let substituted = self.substitute_pattern(q, &acc, &ex);
+                    if let Some(sub) = self.substitution.take() { renames.extend(sub.renames) };
+                    substituted
+                });
+                // Traced as a single substitution of the whole value for the pattern
+                if self.tracer.is_some() {
+                    self.substitution = Some(Substitution { var: p.clone(), value: v.clone(), body: body.clone(), renames });
+                }
+                result
+            }
+        }
+    }
+    // Substitute fields for case variables, variables not yet replaced are binders so they are renamed if they would capture a field
+    fn bind_fields(&mut self, vars: &[Ident], fields: Vec<Expression>, body: Expression) -> Expression {
+        let mut rest: Vec<Pattern> = vars.iter().cloned().map(Pattern::Var).collect();
+        let mut body = body;
+        for field in fields {
+            let x = rest.remove(0);
+            let Pattern::Var(Some(x)) = x else { continue };
+            let scope = if rest.is_empty() { body } else { Expression::FuncExpr(rest.clone(), vec![None; rest.len()], Box::new(body)) };
+            match self.substitute(&scope, &x, &field) {
+                Expression::FuncExpr(params, _, ex) if !rest.is_empty() => {
+                    rest = params;
+                    body = *ex
+                },
+                ex => body = ex
+            }
+        }
+        body
+    }
+    // Step a subterm one level deeper, failing before deeply nested terms overflow the stack
+    fn nested(&mut self, expr: &mut Expression, reduce: fn(&mut Self, &mut Expression) -> Result<bool, Error>) -> Result<bool, Error> {
+        // Partially reduced term is filled in by run
+        if self.depth >= self.max_depth { return Err(Error::DepthLimitError(self.max_depth, Box::new(Expression::ErrorExpr), self.span)) }
+        self.depth += 1;
+        let stepped = reduce(self, expr);
+        self.depth -= 1;
+        stepped
+    }
+    fn step(&mut self, expr: &mut Expression) -> Result<bool, Error> {
+        self.nested(expr, Self::reduce_env)
+    }
+    fn step_subst(&mut self, expr: &mut Expression) -> Result<bool, Error> {
+        self.nested(expr, Self::reduce_subst)
+    }
+    fn reduce_env(&mut self, expr: &mut Expression) -> Result<bool, Error> {
         match expr {
             Expression::ValExpr(v) => {
                 match v {
@@ -99,12 +502,10 @@ impl Evaluator {
                         // Available
                         Some(ex) => {
                             // Check if available expression is an ident with the same name
-                            let pulled_ident = match &ex {
-                                Expression::ValExpr(Value::Identifier(_)) => true,
-                                _ => false
-                            };
+                            let pulled_ident = matches!(&ex, Expression::ValExpr(Value::Identifier(_)));
                             // Update self
                             *expr = ex;
+                            if !pulled_ident { self.rule = Some(Rule::Lookup) };
                             // Return
                             Ok(!pulled_ident)
                         },
@@ -120,24 +521,9 @@ impl Evaluator {
                 // If was able to step e1, return true
                 if e1_step { return Ok(true) };
                 // If e1 fully reduced, perform unary operation
-                match op {
-                    Uop::NotUop => {
-                        // Get boolean value from e1
-                        let bool_val = value!(e1.as_ref(), Value::Boolean, "!");
-                        // Update expr
-                        *expr = Expression::ValExpr(Value::Boolean(!bool_val));
-                        // Return true
-                        Ok(true)
-                    },
-                    Uop::NegUop => {
-                        // Get integer value from e1
-                        let int_val = value!(e1.as_ref(), Value::Number, "unary -");
-                        // Update expr
-                        *expr = Expression::ValExpr(Value::Number(-int_val));
-                        // Return true
-                        Ok(true)
-                    }
-                }
+                *expr = self.uop(op, e1)?;
+                // Return true
+                Ok(true)
             },
             Expression::BopExpr(op, e1, e2) => {
                 // Attempt to step e1
@@ -149,22 +535,11 @@ impl Evaluator {
                 // If was able to step e2, return true
                 if e2_step { return Ok(true) };
                 // If both fully reduced, perform binary operation
-                match op {
-                    Bop::PlusBop => bop!(e1, e2, expr, Value::Number, +, "+"),
-                    Bop::MinusBop => bop!(e1, e2, expr, Value::Number, -, "-"),
-                    Bop::TimesBop => bop!(e1, e2, expr, Value::Number, *, "*"),
-                    Bop::DivBop => bop!(e1, e2, expr, Value::Number, /, "/"),
-                    Bop::AndBop => bop!(e1, e2, expr, Value::Boolean, &&, "&"),
-                    Bop::OrBop => bop!(e1, e2, expr, Value::Boolean, ||, "|"),
-                    Bop::XorBop => bop!(e1, e2, expr, Value::Boolean, ^, "^"),
-                    Bop::GtBop => bop!(e1, e2, expr, Value::Number, Value::Boolean, >, ">"),
-                    Bop::GteBop => bop!(e1, e2, expr, Value::Number, Value::Boolean, >=, ">="),
-                    Bop::LtBop => bop!(e1, e2, expr, Value::Number, Value::Boolean, <, "<="),
-                    Bop::LteBop => bop!(e1, e2, expr, Value::Number, Value::Boolean, <=, "<="),
-                    Bop::EqBop => bop!(e1, e2, expr, Value::Number, Value::Boolean, ==, "=")
-                }
+                *expr = self.bop(op, e1, e2)?;
+                // Return true
+                Ok(true)
             },
-            Expression::FuncExpr(params, body) => {
+            Expression::FuncExpr(params, _, body) => {
                 // If using dynamic scope, use empty closure
                 if self.dynamic {
                     *expr = Expression::ValExpr(Value::Closure(params.to_owned(), body.clone(), Environment::new()));
@@ -173,101 +548,755 @@ impl Evaluator {
                 else {
                     *expr = Expression::ValExpr(Value::Closure(params.to_owned(), body.clone(), self.env.clone()));
                 }
+                self.rule = Some(Rule::Closure);
                 Ok(true)
             },
+            Expression::TupleExpr(elist) => {
+                // Step components from left to right
+                for ex in elist.iter_mut() {
+                    if self.step(ex)? { return Ok(true) }
+                }
+                // All components reduced, tuple is a value
+                *expr = Expression::ValExpr(Value::Tuple(std::mem::take(elist)));
+                Ok(true)
+            },
+            Expression::ListExpr(elist) => {
+                // Step elements from left to right
+                for ex in elist.iter_mut() {
+                    if self.step(ex)? { return Ok(true) }
+                }
+                // All elements reduced, list is a value
+                *expr = Expression::ValExpr(Value::List(std::mem::take(elist)));
+                Ok(true)
+            },
+            Expression::ConstructExpr(d, i, elist) => {
+                // Step fields from left to right
+                for ex in elist.iter_mut() {
+                    if self.step(ex)? { return Ok(true) }
+                }
+                // All fields reduced, constructed data is a value
+                *expr = Expression::ValExpr(Value::Data(d.clone(), *i, std::mem::take(elist)));
+                Ok(true)
+            },
+            Expression::MatchExpr(e, cases) => {
+                // Attempt to step scrutinee
+                if self.step(e.as_mut())? { return Ok(true) };
+                // Evaluate body of the matching case with its variables bound
+                let (i, fields) = self.select(e, cases)?;
+                let mut match_env = self.env.clone();
+                for (var, field) in cases[i].vars.iter().zip(fields) {
+                    if let Some(ident) = var { match_env.push(ident, field) };
+                }
+                *expr = Expression::EnvExpr(match_env, Box::new(std::mem::replace(&mut cases[i].body, Expression::ErrorExpr)));
+                Ok(true)
+            },
+            Expression::ProjExpr(e, i, n) => {
+                // Attempt to step tuple
+                if self.step(e.as_mut())? { return Ok(true) };
+                // If tuple fully reduced, select component
+                *expr = self.project(e, *i, *n)?;
+                Ok(true)
+            },
+            Expression::IfExpr(cond, e1, e2) => {
+                // Attempt to step condition
+                let cond_step = self.step(cond.as_mut())?;
+                // If was able to step condition, return true
+                if cond_step { return Ok(true) };
+                // Get boolean value from condition
+                let bool_val = value!(self, cond.as_ref(), Value::Boolean, "if", vec![etype!(cond.as_ref())]);
+                // Replace with taken branch, other branch is never evaluated
+                let branch = if bool_val { e1 } else { e2 };
+                *expr = std::mem::replace(branch.as_mut(), Expression::ValExpr(Value::Unit));
+                self.rule = Some(Rule::If);
+                // Return true
+                Ok(true)
+            },
+            Expression::LetExpr(id, e, body) => {
+                // Attempt to step bound expression, only when evaluating by value
+                if self.strategy == Strategy::Value {
+                    let e_step = self.step(e.as_mut())?;
+                    // If was able to step bound expression, return true
+                    if e_step { return Ok(true) };
+                }
+                // Evaluate body in current environment extended with binding
+                let mut let_env = self.env.clone();
+                for (x, ex) in self.bindings(id, e.as_ref().clone()) { let_env.push(&x, ex) };
+                *expr = Expression::EnvExpr(let_env, std::mem::replace(body, Box::new(Expression::ErrorExpr)));
+                self.rule = Some(Rule::Let);
+                // Return true
+                Ok(true)
+            },
+            Expression::FixExpr(flist, i) => {
+                // Step all functions
+                for f in flist.iter_mut() {
+                    // Attempt to step function
+                    let f_step = self.step(f)?;
+                    // If was able to step function, return true
+                    if f_step { return Ok(true) };
+                }
+                // Check that the selected function is a closure
+                let group_len = flist.len();
+                match &flist[*i] {
+                    Expression::ValExpr(Value::Closure(params, body, env)) => {
+                        // Need one parameter for each function in the group
+                        if params.len() < group_len { return Err(Error::ArityError(params.len(), group_len, self.span)) }
+                        // Clone global env if dynamic, otherwise use closure environment
+                        let mut fix_env = if self.dynamic { self.env.clone() } else { env.clone() };
+                        // Bind each parameter to its own fixpoint, unrolled again when looked up
+                        for (j, p) in params[..group_len].iter().enumerate() {
+                            if let Pattern::Var(Some(ident)) = p { fix_env.push(ident, Expression::FixExpr(flist.clone(), j)) }
+                        }
+                        // Remaining parameters stay in a closure
+                        let rest = params[group_len..].to_vec();
+                        *expr = if rest.is_empty() {
+                            Expression::EnvExpr(fix_env, body.clone())
+                        } else if self.dynamic {
+                            // Closure environments are ignored when dynamic, so apply to the fixpoints inside the body
+                            let mut app = vec![Expression::FuncExpr(params[..group_len].to_vec(), vec![None; group_len], body.clone())];
+                            for j in 0..group_len { app.push(Expression::FixExpr(flist.clone(), j)) };
+                            Expression::ValExpr(Value::Closure(rest, Box::new(Expression::ApplicationExpr(app)), env.clone()))
+                        } else {
+                            Expression::ValExpr(Value::Closure(rest, body.clone(), fix_env))
+                        };
+                        self.rule = Some(Rule::Fix);
+                        // Return true
+                        Ok(true)
+                    },
+                    // Only functions have fixpoints
+                    f => Err(Error::TypeError("fix".to_string(), vec![etype!(f)], self.span))
+                }
+            },
             Expression::ApplicationExpr(alist) => {
-                // Step all items
-                for i in 0..alist.len() {
+                // Step all items, or only the function unless evaluating by value
+                let stepped_len = if self.strategy == Strategy::Value { alist.len() } else { 1 };
+                for item in alist.iter_mut().take(stepped_len) {
                     // Attempt to step item
-                    let e_step = self.step(&mut alist[i])?;
+                    let e_step = self.step(item)?;
                     // If was able to step item, return true
                     if e_step { return Ok(true) };
                 }
                 // Grab first item
-                let first_ref: &Expression = alist.get(0).unwrap();
+                let first_ref: &Expression = alist.first().unwrap();
                 // Check type of first item, return false if not a closure
                 match first_ref {
-                    Expression::ValExpr(v) => match v {
-                        // Is a closure
-                        Value::Closure(params, body, env) => {
-                            // Are there enough arguments to match params?
-                            if alist.len() - 1 < params.len() { return Err("Function not applied to enough parameters".to_string()) }
-                            // Clone global env if dynamic, otherwise use closure environment
-                            let mut closure_env = if self.dynamic { self.env.clone() } else { env.clone() };
-                            // Push params onto closure env
-                            for i in 0..params.len() {
-                                match &params[i] {
-                                    Some(ident) => {
-                                        closure_env.push(ident, alist[i+1].clone())
-                                    },
-                                    None => ()
-                                }
-                            }
-                            // Application result
-                            let app_result = Expression::EnvExpr(closure_env, Box::new(body.as_ref().clone()));
-                            // Did we use all items in the application chain?
-                            if alist.len() - 1 - params.len() > 0 {
-                                // Updated application chain
-                                let mut alist_new = Vec::new();
-                                // Push first application onto head
-                                alist_new.push(app_result);
-                                // Push rest of chain onto tail
-                                for ex in alist[1 + params.len()..].iter() {
-                                    alist_new.push(ex.clone())
-                                };
-                                // Update expression
-                                *expr = Expression::ApplicationExpr(alist_new);
-                            }
-                            // Done with application chain 
-                            else {
-                                *expr = app_result
-                            }
-                            // Return true
-                            Ok(true)
-                        },
-                        _ => Ok(false)
+                    // Is a closure
+                    Expression::ValExpr(Value::Closure(params, body, env)) => {
+                        // Are there enough arguments to match params?
+                        if alist.len() - 1 < params.len() { return Err(Error::ArityError(params.len(), alist.len() - 1, self.span)) }
+                        // Clone global env if dynamic, otherwise use closure environment
+                        let mut closure_env = if self.dynamic { self.env.clone() } else { env.clone() };
+                        // Push params onto closure env
+                        for (i, p) in params.iter().enumerate() {
+                            for (x, ex) in self.bindings(p, alist[i+1].clone()) { closure_env.push(&x, ex) }
+                        }
+                        // Application result
+                        let app_result = Expression::EnvExpr(closure_env, Box::new(body.as_ref().clone()));
+                        // Did we use all items in the application chain?
+                        if alist.len() - 1 - params.len() > 0 {
+                            // Updated application chain
+                            let mut alist_new = Vec::new();
+                            // Push first application onto head
+                            alist_new.push(app_result);
+                            // Push rest of chain onto tail
+                            for ex in alist[1 + params.len()..].iter() {
+                                alist_new.push(ex.clone())
+                            };
+                            // Update expression
+                            *expr = Expression::ApplicationExpr(alist_new);
+                        }
+                        // Done with application chain 
+                        else {
+                            *expr = app_result
+                        }
+                        self.rule = Some(Rule::Beta);
+                        // Return true
+                        Ok(true)
                     },
                     _ => Ok(false)
                 }
             },
             Expression::EnvExpr(env, ex) => {
+                // An environment directly in tail position replaces this one, drop this one so tail calls do not nest
+                let mut tail = ex.as_mut();
+                while let Expression::SpanExpr(_, e) = tail { tail = e.as_mut() }
+                if let Expression::EnvExpr(_, _) = tail {
+                    *expr = std::mem::replace(tail, Expression::ErrorExpr);
+                    return self.step(expr)
+                }
                 // Swap global environment with env
                 std::mem::swap(env, &mut self.env);
                 // Step ex
-                let stepped = self.step(ex.as_mut())?;
-                // Re-swap environments
+                let stepped = self.step(ex.as_mut());
+                // Re-swap environments, also when stepping failed
                 std::mem::swap(env, &mut self.env);
+                let stepped = stepped?;
                 // If finished stepping, get rid of envexpr
                 if !stepped { *expr = ex.as_ref().clone() }
                 // Return stepped value
                 Ok(stepped)
+            },
+            Expression::ThunkExpr(cell) => {
+                // Step shared expression in place so every reference sees the progress
+                let cell = cell.clone();
+                let stepped = match cell.try_borrow_mut() {
+                    Ok(mut inner) => self.step(&mut inner)?,
+                    // Thunk depends on itself, cannot be reduced
+                    Err(_) => false
+                };
+                // If finished stepping, replace with evaluated expression
+                if !stepped { *expr = cell.borrow().clone() }
+                // Return stepped value
+                Ok(stepped)
+            },
+            // Types have no effect at runtime, erase them
+            Expression::TypeFuncExpr(_, ex) | Expression::TypeAppExpr(ex, _) | Expression::DeclExpr(_, ex) => {
+                *expr = std::mem::replace(ex.as_mut(), Expression::ErrorExpr);
+                Ok(true)
+            },
+            // Syntax error node, cannot be reduced
+            Expression::ErrorExpr => Ok(false),
+            Expression::SpanExpr(span, ex) => {
+                // Track innermost span for error reporting
+                let outer_span = std::mem::replace(&mut self.span, *span);
+                // Step ex
+                let stepped = self.step(ex.as_mut());
+                // Restore span
+                self.span = outer_span;
+                let stepped = stepped?;
+                // If finished stepping, get rid of spanexpr
+                if !stepped { *expr = std::mem::replace(ex.as_mut(), Expression::ValExpr(Value::Unit)) }
+                // Return stepped value
+                Ok(stepped)
             }
         }
     }
-    fn eval_expr(&mut self, mut expr: Expression) -> Result<Expression, String> {
+    // Substitute, recording the substitution for tracing
+    fn substitute(&mut self, body: &Expression, x: &str, v: &Expression) -> Expression {
+        let mut renames = Vec::new();
+        let result = substitute_renaming(body, x, v, &mut renames);
+        if self.tracer.is_some() {
+            self.substitution = Some(Substitution { var: Pattern::Var(Some(x.to_string())), value: v.clone(), body: body.clone(), renames });
+        }
+        result
+    }
+    // Step by substitution, reducing leftmost-outermost under lambdas and in branches when normalising
+    fn reduce_subst(&mut self, expr: &mut Expression) -> Result<bool, Error> {
+        // Arguments and let bindings are reduced before substitution when evaluating by value
+        let by_value = !self.normal && self.strategy == Strategy::Value;
+        match expr {
+            Expression::ApplicationExpr(alist) => {
+                // Step function unless it is ready to be applied
+                let is_func = matches!(unspan(&alist[0]), Expression::FuncExpr(params, _, _) if !params.is_empty());
+                if !is_func && self.step_subst(&mut alist[0])? { return Ok(true) };
+                // Step arguments first if evaluating by value, fixpoints are only unrolled when applied
+                if by_value {
+                    for ex in alist[1..].iter_mut() {
+                        if matches!(unspan(ex), Expression::FixExpr(_, _)) { continue }
+                        if self.step_subst(ex)? { return Ok(true) }
+                    }
+                }
+                match unspan(&alist[0]) {
+                    // Function at head, substitute first argument for first parameter
+                    Expression::FuncExpr(params, types, body) if !params.is_empty() => {
+                        let rest = params[1..].to_vec();
+                        let body = if rest.is_empty() { body.as_ref().clone() } else { Expression::FuncExpr(rest, types[1..].to_vec(), body.clone()) };
+                        let result = self.substitute_pattern(&params[0], &body, &alist[1]);
+                        // Apply result to rest of chain
+                        *expr = if alist.len() > 2 {
+                            let mut alist_new = vec![result];
+                            alist_new.extend(alist[2..].iter().cloned());
+                            Expression::ApplicationExpr(alist_new)
+                        } else {
+                            result
+                        };
+                        self.rule = Some(Rule::Beta);
+                        Ok(true)
+                    },
+                    // Otherwise step arguments from left to right when normalising
+                    _ => {
+                        if self.normal {
+                            for ex in alist[1..].iter_mut() {
+                                if self.step_subst(ex)? { return Ok(true) }
+                            }
+                        }
+                        Ok(false)
+                    }
+                }
+            },
+            Expression::FuncExpr(params, types, body) => {
+                // Function bodies are only reduced when normalising
+                if !self.normal { return Ok(false) };
+                // Remove trailing parameters that are only passed on
+                if self.eta {
+                    if let Some(ex) = eta_step(params, types, body.as_ref()) {
+                        *expr = ex;
+                        self.rule = Some(Rule::Eta);
+                        return Ok(true)
+                    }
+                }
+                self.step_subst(body.as_mut())
+            },
+            Expression::FixExpr(flist, i) => {
+                // Unroll, applying function to the fixpoint of every function in the group
+                let mut alist = vec![flist[*i].clone()];
+                for j in 0..flist.len() { alist.push(Expression::FixExpr(flist.clone(), j)) };
+                *expr = Expression::ApplicationExpr(alist);
+                self.rule = Some(Rule::Fix);
+                Ok(true)
+            },
+            Expression::LetExpr(id, e, body) => {
+                // Step bound expression first if evaluating by value
+                if by_value && self.step_subst(e.as_mut())? { return Ok(true) };
+                // Substitute bound expression into body
+                *expr = self.substitute_pattern(id, body.as_ref(), e.as_ref());
+                self.rule = Some(Rule::Let);
+                Ok(true)
+            },
+            Expression::IfExpr(cond, e1, e2) => {
+                // Take branch if condition is a boolean
+                if let Expression::ValExpr(Value::Boolean(b)) = unspan(cond.as_ref()) {
+                    let branch = if *b { e1 } else { e2 };
+                    *expr = std::mem::replace(branch.as_mut(), Expression::ValExpr(Value::Unit));
+                    self.rule = Some(Rule::If);
+                    return Ok(true)
+                }
+                // Step condition
+                if self.step_subst(cond.as_mut())? { return Ok(true) };
+                // Condition must be a boolean, unless normalising and blocked on a variable
+                if !(self.normal && blocked(unspan(cond.as_ref()))) {
+                    value!(self, unspan(cond.as_ref()), Value::Boolean, "if", vec![etype!(unspan(cond.as_ref()))]);
+                }
+                // Step branches
+                if self.step_subst(e1.as_mut())? { return Ok(true) };
+                self.step_subst(e2.as_mut())
+            },
+            Expression::UopExpr(op, e1) => {
+                // Step operand
+                if self.step_subst(e1.as_mut())? { return Ok(true) };
+                // Operations on variables cannot be performed when normalising, list operations only need the list
+                let stuck = match op {
+                    Uop::NegUop | Uop::NotUop => blocked(unspan(e1.as_ref())),
+                    _ => !is_list(unspan(e1.as_ref())) && blocked(unspan(e1.as_ref()))
+                };
+                if self.normal && stuck { return Ok(false) };
+                *expr = self.uop(op, unspan(e1.as_ref()))?;
+                Ok(true)
+            },
+            Expression::BopExpr(op, e1, e2) => {
+                // Step operands
+                if self.step_subst(e1.as_mut())? { return Ok(true) };
+                if self.step_subst(e2.as_mut())? { return Ok(true) };
+                // Operations on variables cannot be performed when normalising, any element can be prepended to a list
+                let stuck = match op {
+                    Bop::ConsBop => !is_list(unspan(e2.as_ref())) && blocked(unspan(e2.as_ref())),
+                    _ => blocked(unspan(e1.as_ref())) || blocked(unspan(e2.as_ref()))
+                };
+                if self.normal && stuck { return Ok(false) };
+                *expr = self.bop(op, unspan(e1.as_ref()), unspan(e2.as_ref()))?;
+                Ok(true)
+            },
+            Expression::TupleExpr(elist) | Expression::ListExpr(elist) => {
+                // Step components from left to right, tuples and lists stay expressions so they can hold variables
+                for ex in elist.iter_mut() {
+                    if self.step_subst(ex)? { return Ok(true) }
+                }
+                Ok(false)
+            },
+            Expression::ConstructExpr(_, _, elist) => {
+                // Step fields from left to right, data stays an expression so it can hold variables
+                for ex in elist.iter_mut() {
+                    if self.step_subst(ex)? { return Ok(true) }
+                }
+                Ok(false)
+            },
+            Expression::MatchExpr(e, cases) => {
+                // Cases are selected without reducing the fields unless evaluating by value
+                let ready = !by_value && constructed(unspan(e.as_ref())).is_some();
+                if !ready && self.step_subst(e.as_mut())? { return Ok(true) };
+                // Matching on a variable cannot be performed when normalising, step case bodies instead
+                if self.normal && constructed(unspan(e.as_ref())).is_none() && blocked(unspan(e.as_ref())) {
+                    for case in cases.iter_mut() {
+                        if self.step_subst(&mut case.body)? { return Ok(true) }
+                    }
+                    return Ok(false)
+                }
+                let (i, fields) = self.select(unspan(e.as_ref()), cases)?;
+                let body = std::mem::replace(&mut cases[i].body, Expression::ErrorExpr);
+                *expr = self.bind_fields(&cases[i].vars, fields, body);
+                Ok(true)
+            },
+            Expression::ProjExpr(e, i, n) => {
+                // Components are selected without reducing the others unless evaluating by value
+                if !by_value && matches!(unspan(e.as_ref()), Expression::TupleExpr(elist) if *i < elist.len()) {
+                    *expr = self.project(unspan(e.as_ref()), *i, *n)?;
+                    return Ok(true)
+                }
+                // Step tuple
+                if self.step_subst(e.as_mut())? { return Ok(true) };
+                // Projections of variables cannot be performed when normalising
+                if self.normal && blocked(unspan(e.as_ref())) { return Ok(false) };
+                *expr = self.project(unspan(e.as_ref()), *i, *n)?;
+                Ok(true)
+            },
+            // Types have no effect at runtime, erase them
+            Expression::TypeFuncExpr(_, ex) | Expression::TypeAppExpr(ex, _) | Expression::DeclExpr(_, ex) => {
+                *expr = std::mem::replace(ex.as_mut(), Expression::ErrorExpr);
+                self.step_subst(expr)
+            },
+            // Span of a reduced redex gives way to the span of its result, so spans do not pile up
+            Expression::SpanExpr(_, ex) if matches!(ex.as_ref(), Expression::SpanExpr(_, _)) => {
+                *expr = std::mem::replace(ex.as_mut(), Expression::ErrorExpr);
+                self.step_subst(expr)
+            },
+            Expression::SpanExpr(span, ex) => {
+                // Track innermost span for error reporting, spans are kept for later steps
+                let outer_span = std::mem::replace(&mut self.span, *span);
+                let stepped = self.step_subst(ex.as_mut());
+                self.span = outer_span;
+                stepped
+            },
+            // Variables, values, and errors cannot be reduced
+            _ => Ok(false)
+        }
+    }
+    // Reduce expression until fixed point
+    fn eval_expr(&mut self, expr: Expression) -> Result<Expression, Error> {
+        self.run(expr, Self::step)
+    }
+    fn run(&mut self, mut expr: Expression, step: fn(&mut Self, &mut Expression) -> Result<bool, Error>) -> Result<Expression, Error> {
+        // Report initial expression
+        if let Some(t) = self.tracer.as_mut() { t(&Step { number: self.steps, rule: None, substitution: None, expr: &expr }) };
+        // Limit errors point at the whole expression
+        let span = match &expr { Expression::SpanExpr(s, _) => *s, _ => self.span };
         // Reduce expression until fixed point
-        loop { if !(self.step(&mut expr)?) { break } };
+        loop {
+            // Stop if out of time, reporting the partially reduced term
+            if self.deadline.is_some_and(|d| Instant::now() >= d) {
+                return Err(Error::TimeoutError(self.timeout.unwrap_or_default(), Box::new(expr), span))
+            }
+            // Out of steps, keep the term in case another step fires, a reduced term is still returned
+            let spent = if self.max_steps.is_some_and(|m| self.steps >= m) { Some(expr.clone()) } else { None };
+            self.rule = None;
+            self.substitution = None;
+            let stepped = match step(self, &mut expr) {
+                // Report the whole partially reduced term
+                Err(Error::DepthLimitError(depth, _, _)) => return Err(Error::DepthLimitError(depth, Box::new(expr), span)),
+                stepped => stepped?
+            };
+            if !stepped { break }
+            // Report step, skipping administrative steps that fire no rule
+            if let Some(rule) = self.rule {
+                if let Some(before) = spent { return Err(Error::StepLimitError(self.steps, Box::new(before), span)) }
+                self.steps += 1;
+                let substitution = self.substitution.take();
+                if let Some(t) = self.tracer.as_mut() { t(&Step { number: self.steps, rule: Some(rule), substitution, expr: &expr }) };
+            }
+        };
         // Return reduced expression
         Ok(expr)
     }
-    pub fn eval_program(&mut self, prog: Program, dynamic: bool) -> Result<Expression, String> {
+    pub fn eval_program(&mut self, prog: Program, dynamic: bool) -> Result<Expression, Error> {
         // Clear environment
         self.env.clear();
         // Set scoping rules
         self.dynamic = dynamic;
+        // Reset limits
+        self.start();
+        self.normal = false;
+        // Substitute statements like let expressions
+        if self.engine == Engine::Subst { return self.run(nest(prog), Self::step_subst) }
+        // When tracing, evaluate as a single expression so each step shows the rest of the program
+        if self.tracer.is_some() { return self.eval_expr(nest(prog)) }
         // Statements
-        for stmt in prog.0 {
-            match stmt.0 {
-                Some(ident) => {
-                    // Evaluate expression
-                    let eval_e = self.eval_expr(stmt.1)?;
-                    // Store in environment
-                    self.env.push(&ident, eval_e)
-                },
-                None => ()
-            }
-        };
+        self.eval_statements(prog.0)?;
         // Program body
         self.eval_expr(prog.1)
     }
-}
\ No newline at end of file
+    // Reduce program to normal form in the pure calculus
+    pub fn normalize_program(&mut self, prog: Program) -> Result<Expression, Error> {
+        // Reset limits
+        self.start();
+        self.normal = true;
+        // Statements are substituted like let expressions
+        self.run(nest(prog), Self::step_subst)
+    }
+    // Evaluate interactive input on top of the existing environment
+    pub fn eval_repl(&mut self, input: (Vec<Statement>, Option<Expression>), dynamic: bool) -> Result<Option<Expression>, Error> {
+        // Set scoping rules
+        self.dynamic = dynamic;
+        // Reset limits
+        self.start();
+        // Environment to restore if the input fails, so that none of its statements are kept
+        let saved = self.env.clone();
+        let result = self.eval_statements(input.0).and_then(|_| match input.1 {
+            // Optional body
+            Some(ex) => Ok(Some(self.eval_expr(ex)?)),
+            None => Ok(None)
+        });
+        if result.is_err() { self.env = saved }
+        result
+    }
+    fn eval_statements(&mut self, stmts: Vec<Statement>) -> Result<(), Error> {
+        for stmt in stmts {
+            if stmt.0.vars().is_empty() { continue }
+            // Evaluate expression unless evaluating by value, bindings delay it otherwise
+            let eval_e = match self.strategy {
+                Strategy::Value => self.eval_expr(stmt.1)?,
+                _ => stmt.1
+            };
+            // Store in environment
+            for (x, ex) in self.bindings(&stmt.0, eval_e) { self.env.push(&x, ex) }
+        };
+        Ok(())
+    }
+    pub fn env(&self) -> &Environment {
+        &self.env
+    }
+    pub fn reset(&mut self) {
+        self.env.clear()
+    }
+}
+
+// Program as a single expression, with statements as nested let expressions
+pub fn nest(prog: Program) -> Expression {
+    // Span of the body, errors about the whole program point at it
+    let span = match (&prog.1, prog.0.is_empty()) {
+        (Expression::SpanExpr(s, _), false) => Some(*s),
+        _ => None
+    };
+    let e = prog.0.into_iter().rev().fold(prog.1, |acc, s| match declared(&s) {
+        // Declarations scope over the rest of the program
+        Some(d) => Expression::DeclExpr(d.clone(), Box::new(acc)),
+        None => Expression::LetExpr(s.0, Box::new(s.1), Box::new(acc))
+    });
+    match span {
+        Some(s) => Expression::SpanExpr(s, Box::new(e)),
+        None => e
+    }
+}
+
+// Normal form that cannot be used by an operation, because it depends on a variable
+fn blocked(e: &Expression) -> bool {
+    match e {
+        // Tuples, lists, and data are compared by their components
+        Expression::TupleExpr(elist) | Expression::ListExpr(elist) | Expression::ConstructExpr(_, _, elist) |
+        Expression::ValExpr(Value::Tuple(elist) | Value::List(elist) | Value::Data(_, _, elist)) => {
+            elist.iter().any(|ex| blocked(unspan(ex)))
+        },
+        _ => !matches!(e, Expression::FuncExpr(_, _, _) | Expression::ValExpr(Value::Number(_) | Value::Boolean(_) | Value::Unit))
+    }
+}
+
+// Whether a normal form is a list
+fn is_list(e: &Expression) -> bool {
+    matches!(e, Expression::ListExpr(_) | Expression::ValExpr(Value::List(_)))
+}
+
+// List with new elements, an expression or a value like the list it was made from
+fn rebuild(e: &Expression, elist: Vec<Expression>) -> Expression {
+    match e {
+        Expression::ValExpr(_) => Expression::ValExpr(Value::List(elist)),
+        _ => Expression::ListExpr(elist)
+    }
+}
+
+// Declaration, constructor index, and fields of a data expression or value
+fn constructed(e: &Expression) -> Option<(&Rc<DataType>, usize, &Vec<Expression>)> {
+    match e {
+        Expression::ConstructExpr(d, i, elist) | Expression::ValExpr(Value::Data(d, i, elist)) => Some((d, *i, elist)),
+        _ => None
+    }
+}
+
+// Components of a tuple expression or value
+fn components(e: &Expression) -> Option<&Vec<Expression>> {
+    match e {
+        Expression::TupleExpr(elist) | Expression::ValExpr(Value::Tuple(elist)) => Some(elist),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, printing::grouped_expression_to_string};
+
+    fn parse(src: &str) -> Program {
+        let tokens = Lexer::new().generate(src).unwrap();
+        Parser::new(false, false).parse_program(tokens).unwrap()
+    }
+
+    fn eval(src: &str, strategy: Strategy, max_steps: Option<usize>) -> Result<String, Error> {
+        let mut evaluate = Evaluator::new();
+        evaluate.set_strategy(strategy);
+        evaluate.set_max_steps(max_steps);
+        evaluate.eval_program(parse(src), false).map(|e| grouped_expression_to_string(&e, false))
+    }
+
+    fn normalize(src: &str) -> String {
+        let mut evaluate = Evaluator::new();
+        grouped_expression_to_string(&evaluate.normalize_program(parse(src)).unwrap(), false)
+    }
+
+    // Argument that never finishes reducing
+    const LOOP: &str = r"let rec loop = \x. loop x in (\x. 0) (loop 1)";
+
+    #[test]
+    fn normal_form_avoids_capture() {
+        assert_eq!(normalize(r"(\x y. x) y"), r"(\y'. y)");
+        assert_eq!(normalize(r"\z. (\x y. x y) z"), r"(\z. (\y. z y))");
+    }
+
+    #[test]
+    fn unused_divergent_argument_is_skipped_unless_by_value() {
+        assert_eq!(eval(LOOP, Strategy::Name, Some(1000)).unwrap(), "0");
+        assert_eq!(eval(LOOP, Strategy::Need, Some(1000)).unwrap(), "0");
+        assert!(matches!(eval(LOOP, Strategy::Value, Some(1000)), Err(Error::StepLimitError(1000, _, _))));
+    }
+
+    #[test]
+    fn need_shares_argument_reductions() {
+        // The argument is reduced once by need and once per use by name
+        let src = r"(\x. x + x) ((\y. y * 2) 5)";
+        assert_eq!(eval(src, Strategy::Need, Some(9)).unwrap(), "20");
+        assert!(eval(src, Strategy::Name, Some(9)).is_err());
+        assert_eq!(eval(src, Strategy::Name, Some(13)).unwrap(), "20");
+    }
+
+    #[test]
+    fn step_limit_allows_exactly_the_budget() {
+        let src = "if 1 < 2 then 3 else 4";
+        assert_eq!(eval(src, Strategy::Value, Some(2)).unwrap(), "3");
+        assert!(matches!(eval(src, Strategy::Value, Some(1)), Err(Error::StepLimitError(1, _, _))));
+        // Values need no step
+        assert_eq!(eval("5", Strategy::Value, Some(0)).unwrap(), "5");
+    }
+
+    #[test]
+    fn substitution_engine_agrees_with_environments() {
+        let src = r"let rec fact = \n. if n = 0 then 1 else n * (fact (n - 1)) in fact 5";
+        let mut evaluate = Evaluator::new();
+        evaluate.set_engine(Engine::Subst);
+        let e = evaluate.eval_program(parse(src), false).unwrap();
+        assert_eq!(grouped_expression_to_string(&e, false), eval(src, Strategy::Value, None).unwrap());
+    }
+
+    #[test]
+    fn tuple_patterns_bind_components() {
+        let src = r"let (a, b) = (1, (2, (3, 4))) in (\(x, (y, z)) w. x + y + z + w) b a";
+        for strategy in [Strategy::Value, Strategy::Name, Strategy::Need] {
+            assert_eq!(eval(src, strategy, None).unwrap(), "10");
+        }
+        // Components free in the value are not captured by the other variables of the pattern
+        assert_eq!(normalize(r"\a b. (\(a, b). a b) (b, a)"), r"(\a b. b a)");
+    }
+
+    #[test]
+    fn projection_checks_tuple_size_at_runtime() {
+        for src in ["fst (1, 2, 3)", r"(\(a, b). a) (1, 2, 3)"] {
+            assert!(matches!(eval(src, Strategy::Value, None), Err(Error::TypeError(_, _, _))));
+        }
+        assert_eq!(eval("(1, 2, 3).2", Strategy::Value, None).unwrap(), "3");
+    }
+
+    #[test]
+    fn cons_reports_the_right_operand_when_it_is_not_a_list() {
+        match eval("1 :: 2", Strategy::Value, None) {
+            Err(Error::TypeError(op, types, _)) => assert_eq!((op.as_str(), types), (":: onto a list", vec!["int".to_string()])),
+            r => panic!("unexpected {:?}", r)
+        }
+    }
+
+    #[test]
+    fn failed_repl_input_keeps_no_statements() {
+        let mut evaluate = Evaluator::new();
+        let mut parse = Parser::new(false, false);
+        let mut input = |src: &str| parse.parse_repl(Lexer::new().generate(src).unwrap()).unwrap();
+        evaluate.eval_repl(input("let x = 1 in"), false).unwrap();
+        assert!(evaluate.eval_repl(input("let y = 2 in y + true"), false).is_err());
+        let names: Vec<&str> = evaluate.env().data().iter().map(|(x, _)| x.as_str()).collect();
+        assert_eq!(names, vec!["x"]);
+    }
+
+    #[test]
+    fn step_limit_points_at_program_body() {
+        let prog = parse(&format!("let x = 1 in\n{}", LOOP));
+        let Expression::SpanExpr(body, _) = prog.1 else { panic!("body has no span") };
+        for engine in [Engine::Env, Engine::Subst] {
+            let mut evaluate = Evaluator::new();
+            evaluate.set_engine(engine);
+            evaluate.set_max_steps(Some(10));
+            assert_eq!(evaluate.eval_program(prog.clone(), false).unwrap_err().span(), body);
+        }
+    }
+
+    #[test]
+    fn keywords_standing_alone_are_functions() {
+        assert_eq!(eval("let f = head in f [1, 2]", Strategy::Value, None).unwrap(), "1");
+        assert_eq!(eval("(fst, snd).1 (1, 2)", Strategy::Value, None).unwrap(), "2");
+        assert_eq!(eval("let c = cons 1 in c [2]", Strategy::Value, None).unwrap(), "[1, 2]");
+    }
+
+    #[test]
+    fn deep_recursion_stops_at_depth_limit() {
+        let src = r"let rec s = \n. if n = 0 then 0 else n + (s (n - 1)) in s 100";
+        let mut evaluate = Evaluator::new();
+        evaluate.set_max_depth(50);
+        let Err(Error::DepthLimitError(50, term, _)) = evaluate.eval_program(parse(src), false) else { panic!("depth limit not reached") };
+        assert!(grouped_expression_to_string(&term, false).starts_with("100 + (99 + "));
+        // Limit is per evaluation, shallow terms still evaluate afterwards
+        assert_eq!(evaluate.eval_program(parse("1 + 2"), false).map(|e| grouped_expression_to_string(&e, false)).unwrap(), "3");
+    }
+
+    #[test]
+    fn lists_are_built_and_taken_apart() {
+        for strategy in [Strategy::Value, Strategy::Name, Strategy::Need] {
+            assert_eq!(eval("1 :: 2 :: nil", strategy, None).unwrap(), "[1, 2]");
+            assert_eq!(eval("let l = [1, 2, 3] in head (tail l)", strategy, None).unwrap(), "2");
+            assert_eq!(eval("(isnil nil, isnil [1], tail [1])", strategy, None).unwrap(), "(true, false, [])");
+        }
+        assert_eq!(eval("[1 + 1, 3] = 2 :: [3]", Strategy::Value, None).unwrap(), "true");
+        assert_eq!(eval("[[1], []] = [[1]]", Strategy::Value, None).unwrap(), "false");
+    }
+
+    #[test]
+    fn empty_list_has_no_head_or_tail() {
+        assert!(matches!(eval("head []", Strategy::Value, None), Err(Error::EmptyListError("head", _))));
+        assert!(matches!(eval("tail (tail [1])", Strategy::Value, None), Err(Error::EmptyListError("tail", _))));
+    }
+
+    #[test]
+    fn list_operations_agree_across_engines() {
+        let src = r"let rec sum = \l. if isnil l then 0 else (head l) + (sum (tail l)) in sum (cons 1 [2, 3])";
+        for engine in [Engine::Env, Engine::Subst] {
+            let mut evaluate = Evaluator::new();
+            evaluate.set_engine(engine);
+            assert_eq!(grouped_expression_to_string(&evaluate.eval_program(parse(src), false).unwrap(), false), "6");
+        }
+    }
+
+    #[test]
+    fn match_selects_the_case_of_the_constructor() {
+        let src = r"type option = None | Some a in (match Some (1 + 1) with None -> 0 | Some x -> x * 10)";
+        for strategy in [Strategy::Value, Strategy::Name, Strategy::Need] {
+            assert_eq!(eval(src, strategy, None).unwrap(), "20");
+        }
+        // Variable case matches any value
+        assert_eq!(eval("type t = A | B int in (match B 1 with A -> 0 | other -> 2)", Strategy::Value, None).unwrap(), "2");
+    }
+
+    #[test]
+    fn recursive_data_types_are_traversed() {
+        let src = r"type l = Nil | Cons int l in
+            let rec len = \x. match x with Nil -> 0 | Cons h t -> 1 + (len t) in len (Cons 1 (Cons 2 Nil))";
+        assert_eq!(eval(src, Strategy::Value, None).unwrap(), "2");
+    }
+
+    #[test]
+    fn no_matching_case_reports_the_value() {
+        match eval("type t = A | B int in (match B 3 with A -> 0)", Strategy::Value, None) {
+            Err(Error::MatchError(v, _)) => assert_eq!(grouped_expression_to_string(&v, false), "B 3"),
+            r => panic!("unexpected {:?}", r)
+        }
+    }
+}
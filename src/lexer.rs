@@ -1,5 +1,6 @@
 use regex::Regex;
 use crate::types::token::*;
+use crate::error::Error;
 
 // Macro that puts carrot at the beginning of regex
 macro_rules! reg {
@@ -15,7 +16,8 @@ type ValueConstructor = fn(&str) -> TokenValue;
 enum VariantOption {
     Some(Variant, ValueConstructor),
     None,
-    Newline
+    Newline,
+    Comment
 }
 
 // Value constructor functions
@@ -26,17 +28,35 @@ fn value_bool_t(_: &str) -> TokenValue { TokenValue::Boolean(true) }
 fn value_bool_f(_: &str) -> TokenValue { TokenValue::Boolean(false) }
 
 // Number to available tokens
-const TOKEN_COUNT: usize = 26;
+const TOKEN_COUNT: usize = 51;
 
 // Tokens
 const TOKENS: [(&str, VariantOption); TOKEN_COUNT] = [
     // Keywords
-    (reg!(r"\\"), VariantOption::Some(Variant::Lambda, value_none)),
+    (reg!(r"[\\λ]"), VariantOption::Some(Variant::Lambda, value_none)),
+    (reg!(r"(?:/\\|Λ)"), VariantOption::Some(Variant::TypeLambda, value_none)),
     (reg!(r"\."), VariantOption::Some(Variant::Dot, value_none)),
     (reg!(r"let"), VariantOption::Some(Variant::Let, value_none)),
     (reg!(r"in"), VariantOption::Some(Variant::In, value_none)),
+    (reg!(r"if"), VariantOption::Some(Variant::If, value_none)),
+    (reg!(r"then"), VariantOption::Some(Variant::Then, value_none)),
+    (reg!(r"else"), VariantOption::Some(Variant::Else, value_none)),
+    (reg!(r"rec"), VariantOption::Some(Variant::Rec, value_none)),
+    (reg!(r"and"), VariantOption::Some(Variant::RecAnd, value_none)),
+    (reg!(r"fix"), VariantOption::Some(Variant::Fix, value_none)),
+    (reg!(r"fst"), VariantOption::Some(Variant::Fst, value_none)),
+    (reg!(r"snd"), VariantOption::Some(Variant::Snd, value_none)),
+    (reg!(r"cons"), VariantOption::Some(Variant::Cons, value_none)),
+    (reg!(r"nil"), VariantOption::Some(Variant::Nil, value_none)),
+    (reg!(r"head"), VariantOption::Some(Variant::Head, value_none)),
+    (reg!(r"tail"), VariantOption::Some(Variant::Tail, value_none)),
+    (reg!(r"isnil"), VariantOption::Some(Variant::IsNil, value_none)),
+    (reg!(r"type"), VariantOption::Some(Variant::Type, value_none)),
+    (reg!(r"match"), VariantOption::Some(Variant::Match, value_none)),
+    (reg!(r"with"), VariantOption::Some(Variant::With, value_none)),
     (reg!(r"\("), VariantOption::Some(Variant::LParen, value_none)),
     (reg!(r"\)"), VariantOption::Some(Variant::RParen, value_none)),
+    (reg!(r","), VariantOption::Some(Variant::Comma, value_none)),
     (reg!(r"true"), VariantOption::Some(Variant::Boolean, value_bool_t)),
     (reg!(r"false"), VariantOption::Some(Variant::Boolean, value_bool_f)),
     (reg!(r"_"), VariantOption::Some(Variant::Unit, value_none)),
@@ -54,13 +74,23 @@ const TOKENS: [(&str, VariantOption); TOKEN_COUNT] = [
     (reg!(r"&"), VariantOption::Some(Variant::And, value_none)),
     (reg!(r"\|"), VariantOption::Some(Variant::Or, value_none)),
     (reg!(r"\^"), VariantOption::Some(Variant::Xor, value_none)),
+    (reg!(r"::"), VariantOption::Some(Variant::DoubleColon, value_none)),
+    // Type annotations
+    (reg!(r":"), VariantOption::Some(Variant::Colon, value_none)),
+    (reg!(r"->"), VariantOption::Some(Variant::Arrow, value_none)),
+    (reg!(r"\["), VariantOption::Some(Variant::LBracket, value_none)),
+    (reg!(r"\]"), VariantOption::Some(Variant::RBracket, value_none)),
     // Numbers
     (reg!(r"[0-9]+"), VariantOption::Some(Variant::Number, value_number)),
     // Identifiers
-    (reg!(r"[a-zA-Z]+"), VariantOption::Some(Variant::Ident, value_ident)),
+    // Letters (except lambdas) and underscores, followed by letters, digits, underscores, and primes
+    (reg!(r"[[\p{L}_]--[λΛ]][[\p{L}\p{N}_']--[λΛ]]*"), VariantOption::Some(Variant::Ident, value_ident)),
+    // Comments
+    (reg!(r"--[^\n]*"), VariantOption::None),
+    (reg!(r"\{-"), VariantOption::Comment),
     // Special
     (reg!(r"\n"), VariantOption::Newline),
-    (reg!(r"\s+"), VariantOption::None),
+    (reg!(r"[^\S\n]+"), VariantOption::None),
 ];
 
 // Lexer object (singleton)
@@ -70,6 +100,11 @@ pub struct Lexer {
     col: usize,
     tokens: Vec<(Regex, VariantOption)>,
 }
+impl Default for Lexer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl Lexer {
     // Initialize new lexer
     pub fn new() -> Lexer {
@@ -87,59 +122,93 @@ impl Lexer {
         };
         lex
     }
+    // Skip nested block comment, starting after the opening delimiter
+    fn comment(&mut self, stream: &str) -> Result<(), Error> {
+        // Position of opening delimiter
+        let start = (self.row, self.col - 2);
+        // Nesting depth
+        let mut depth = 1;
+        while depth > 0 {
+            let rest = &stream[self.pos..];
+            // Reached end of stream inside comment
+            if rest.is_empty() {
+                return Err(Error::CommentError((start, (start.0, start.1 + 2))))
+            }
+            // Track nesting and position
+            if rest.starts_with("{-") || rest.starts_with("-}") {
+                depth = if rest.starts_with("{-") { depth + 1 } else { depth - 1 };
+                self.pos += 2;
+                self.col += 2;
+            } else {
+                let c = rest.chars().next().unwrap();
+                if c == '\n' {
+                    self.row += 1;
+                    self.col = 0;
+                } else {
+                    self.col += 1;
+                }
+                self.pos += c.len_utf8();
+            }
+        }
+        Ok(())
+    }
     // Next token in stream
-    fn next(&mut self, stream: &str) -> Option<Token> {
+    fn next(&mut self, stream: &str) -> Result<Option<Token>, Error> {
         // Length of longest match
         let mut longest_match: usize = 0;
         // Index pointing to variant of longest match (initialize to zero, doesn't really matter)
         let mut longest_variant: usize = 0;
         // Iterate through each token, find longest match
         for (i, token_def) in self.tokens.iter().enumerate() {
-            match token_def.0.find(&stream[self.pos..]) {
-                Some(m) => {
-                    if m.len() > longest_match {
-                        longest_match = m.len();
-                        longest_variant = i;
-                    }
-                },
-                None => ()
+            if let Some(m) = token_def.0.find(&stream[self.pos..]) {
+                if m.len() > longest_match {
+                    longest_match = m.len();
+                    longest_variant = i;
+                }
             }
         };
         // If found token
         if longest_match > 0 {
+            // Columns count characters, not bytes
+            let match_chars = stream[self.pos..(self.pos + longest_match)].chars().count();
             // Update col
-            self.col += longest_match;
+            self.col += match_chars;
             // Update position
             self.pos += longest_match;
             // Check matched token
             match &self.tokens.get(longest_variant).unwrap().1 {
                 // If matched usable token, get value and return
                 VariantOption::Some(var, producer) => {
-                    Some((
+                    Ok(Some((
                         var.clone(), // Token variant
                         producer(&stream[(self.pos-longest_match)..self.pos]), // Token value
-                        (self.row, self.col - longest_match) // Token position (need to revert to old col)
-                    ))
+                        ((self.row, self.col - match_chars), (self.row, self.col)) // Token span (need to revert to old col)
+                    )))
                 },
                 // If matched throwaway token, return none
                 VariantOption::None => {
-                    None
+                    Ok(None)
                 },
                 // If matched newline, update row and column then return none
                 VariantOption::Newline => {
                     self.row += 1;
                     self.col = 0;
-                    None
+                    Ok(None)
+                },
+                // If matched start of block comment, skip comment then return none
+                VariantOption::Comment => {
+                    self.comment(stream)?;
+                    Ok(None)
                 }
             }
         }
         // Did not find token, return none
         else {
-            None
+            Ok(None)
         }
     }
     // Generate stream of tokens
-    pub fn generate(&mut self, stream: &str) -> Result<Vec<Token>, String> {
+    pub fn generate(&mut self, stream: &str) -> Result<Vec<Token>, Error> {
         // Reset pos, row, and column
         self.pos = 0;
         self.row = 0;
@@ -155,7 +224,7 @@ impl Lexer {
             // Save old position
             let old_pos = self.pos;
             // Generate next token
-            let next_token = self.next(stream);
+            let next_token = self.next(stream)?;
             // Match next token
             match next_token {
                 Some(t) => {
@@ -165,14 +234,69 @@ impl Lexer {
                 None => {
                     // If current position = old position, error
                     if self.pos == old_pos {
-                        return Err("Unexpected character at ".to_string() + (self.row + 1).to_string().as_ref() + ":" + (self.col + 1).to_string().as_ref())
+                        let c = stream[self.pos..].chars().next().unwrap();
+                        return Err(Error::LexError(c, ((self.row, self.col), (self.row, self.col + 1))))
                     }
                 }
             }
         }
         // Add EOF to end of stream
-        tokens.push((Variant::EOF, TokenValue::None, (self.row, self.col)));
+        tokens.push((Variant::EOF, TokenValue::None, ((self.row, self.col), (self.row, self.col))));
         // Return
         Ok(tokens)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variants(src: &str) -> Vec<Variant> {
+        Lexer::new().generate(src).unwrap().into_iter().map(|t| t.0).collect()
+    }
+
+    #[test]
+    fn line_comment_runs_to_end_of_line() {
+        assert_eq!(variants("1 -- 2 + 3\n+ 4"), vec![Variant::Number, Variant::Plus, Variant::Number, Variant::EOF]);
+    }
+
+    #[test]
+    fn block_comments_nest() {
+        assert_eq!(variants("{- a {- b -} c -} 1 {--}"), vec![Variant::Number, Variant::EOF]);
+    }
+
+    #[test]
+    fn positions_continue_after_multi_line_comment() {
+        let tokens = Lexer::new().generate("{- one\ntwo -} x").unwrap();
+        assert_eq!(tokens[0].2, ((1, 7), (1, 8)));
+    }
+
+    #[test]
+    fn unterminated_comment_points_at_its_start() {
+        let result = Lexer::new().generate("x\n  {- a {- b -}");
+        assert!(matches!(result, Err(Error::CommentError(((1, 2), (1, 4))))));
+    }
+
+    fn idents(src: &str) -> Vec<String> {
+        Lexer::new().generate(src).unwrap().into_iter().filter_map(|t| match t.1 {
+            TokenValue::Str(x) => Some(x),
+            _ => None
+        }).collect()
+    }
+
+    #[test]
+    fn identifiers_take_digits_underscores_primes_and_unicode() {
+        assert_eq!(idents("x1 f' is_zero succ2 _tmp αβ x₁"), vec!["x1", "f'", "is_zero", "succ2", "_tmp", "αβ", "x₁"]);
+    }
+
+    #[test]
+    fn bare_underscore_stays_the_wildcard() {
+        assert_eq!(variants("_ __"), vec![Variant::Unit, Variant::Ident, Variant::EOF]);
+    }
+
+    #[test]
+    fn lambda_letter_is_a_lambda() {
+        assert_eq!(variants("λx. x"), variants(r"\x. x"));
+        // Columns count characters
+        assert_eq!(Lexer::new().generate("λx").unwrap()[1].2, ((0, 1), (0, 2)));
+    }
+}
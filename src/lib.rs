@@ -0,0 +1,263 @@
+pub mod types;
+pub mod error;
+pub mod diagnostic;
+pub mod lexer;
+pub mod parser;
+pub mod typechecker;
+pub mod systemf;
+pub mod evaluator;
+pub mod substitution;
+pub mod debruijn;
+pub mod eta;
+pub mod equivalence;
+pub mod printing;
+
+use std::time::Duration;
+use error::Error;
+use evaluator::{Engine, Evaluator, Strategy, Tracer};
+use types::{ast::{Expression, Program}, token::Token};
+
+// Step budget for normalising each program when comparing, unless a step limit is set
+pub const EQUIV_STEPS: usize = 10000;
+
+// Part of the pipeline to run on a program, each prints its result
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    // Token stream
+    Lex,
+    // Abstract syntax tree
+    Ast,
+    // Program with every group parenthesized
+    Group,
+    // Type of each statement and of the body
+    Types,
+    // Nameless program
+    Debruijn,
+    // Program with functions eta reduced
+    EtaReduce,
+    // Program with functions eta expanded
+    EtaExpand,
+    // Value of the program
+    Eval,
+    // Normal form in the pure calculus
+    Normal
+}
+
+// Settings that cannot be used together
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Conflict {
+    // Substitution shares no arguments
+    NeedWithSubstitution,
+    // Substitution keeps no environment to scope dynamically
+    DynamicWithSubstitution
+}
+
+// Settings for every part of the pipeline
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub stage: Stage,
+    // Disable precedence rules for binary operators
+    pub noprec: bool,
+    // Use rightmost associativity for binary operators
+    pub right: bool,
+    // Programs are in System F, checked against their annotations and then erased
+    pub systemf: bool,
+    // Check types before evaluating
+    pub typecheck: bool,
+    // Use dynamic scoping rules in the evaluator
+    pub dynamic: bool,
+    pub strategy: Strategy,
+    pub engine: Engine,
+    // Eta reduce functions when normalising
+    pub eta: bool,
+    pub max_steps: Option<usize>,
+    pub timeout: Option<Duration>,
+    // Recover from syntax errors to report all of them
+    pub recover: bool,
+    // Number de Bruijn indices from 0 or 1
+    pub index_base: usize
+}
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            stage: Stage::Eval,
+            noprec: false,
+            right: false,
+            systemf: false,
+            typecheck: false,
+            dynamic: false,
+            strategy: Strategy::Value,
+            engine: Engine::Env,
+            eta: false,
+            max_steps: None,
+            timeout: None,
+            recover: false,
+            index_base: 0
+        }
+    }
+}
+impl Options {
+    // Pipeline for these settings, normalisation always substitutes so it is checked like the substitution engine
+    pub fn build(self) -> Result<Pipeline, Conflict> {
+        if self.engine == Engine::Subst || self.stage == Stage::Normal {
+            if self.strategy == Strategy::Need { return Err(Conflict::NeedWithSubstitution) }
+            if self.dynamic { return Err(Conflict::DynamicWithSubstitution) }
+        }
+        // Configure evaluator once, so a tracer set on the pipeline sees every step
+        let mut evaluate = Evaluator::new();
+        evaluate.set_engine(self.engine);
+        evaluate.set_strategy(self.strategy);
+        evaluate.set_eta(self.eta);
+        evaluate.set_max_steps(self.max_steps);
+        evaluate.set_timeout(self.timeout);
+        Ok(Pipeline { options: self, evaluate, tracing: false })
+    }
+}
+
+// Lexer, parser, type checker, and evaluator configured by a valid set of options
+pub struct Pipeline {
+    options: Options,
+    evaluate: Evaluator,
+    // Result of evaluation is the last traced step, so it is not printed again
+    tracing: bool
+}
+impl Pipeline {
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+    // Report each reduction step to tracer
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracing = tracer.is_some();
+        self.evaluate.set_tracer(tracer)
+    }
+    // Parser for the configured operators and calculus
+    pub fn parser(&self) -> parser::Parser {
+        let mut parse = parser::Parser::new(self.options.noprec, self.options.right);
+        parse.set_systemf(self.options.systemf);
+        parse
+    }
+    // Evaluator keeping the configured settings, for interactive use
+    pub fn into_evaluator(self) -> Evaluator {
+        self.evaluate
+    }
+    // Lex input into a token stream
+    pub fn lex(&self, input: &str) -> Result<Vec<Token>, Error> {
+        lexer::Lexer::new().generate(input)
+    }
+    // Lex and parse input into a program, with every syntax error when recovering
+    pub fn parse(&self, input: &str) -> Result<Program, Vec<Error>> {
+        let tokens = self.lex(input).map_err(|e| vec![e])?;
+        if !self.options.recover { return self.parser().parse_program(tokens).map_err(|e| vec![e]) }
+        let (prog, errors) = self.parser().parse_program_recover(tokens);
+        if errors.is_empty() { Ok(prog) } else { Err(errors) }
+    }
+    // Types of statements and body, System F programs are checked against their annotations and others inferred
+    pub fn typecheck(&self, prog: &Program) -> Result<typechecker::ProgramTypes, Error> {
+        if self.options.systemf {
+            systemf::Checker::new().check_program(prog)
+        } else {
+            typechecker::TypeChecker::new().check_program(prog)
+        }
+    }
+    // Check types first if requested, System F programs are always checked and then erased
+    fn checked(&self, prog: Program) -> Result<Program, Error> {
+        if self.options.typecheck || self.options.systemf { self.typecheck(&prog)?; }
+        Ok(if self.options.systemf { systemf::erase_program(prog) } else { prog })
+    }
+    // Evaluate a program
+    pub fn evaluate(&mut self, prog: Program) -> Result<Expression, Error> {
+        let prog = self.checked(prog)?;
+        self.evaluate.eval_program(prog, self.options.dynamic)
+    }
+    // Reduce a program to normal form in the pure calculus
+    pub fn normalize(&mut self, prog: Program) -> Result<Expression, Error> {
+        let prog = self.checked(prog)?;
+        self.evaluate.normalize_program(prog)
+    }
+    // Normal form within the step budget for comparisons, none if it is not reached
+    pub fn normal_form(&self, prog: Program) -> Result<Option<Expression>, Error> {
+        equivalence::normal_form(prog, self.options.max_steps.unwrap_or(EQUIV_STEPS))
+    }
+    // Compare two programs up to renaming and up to beta-eta conversion
+    pub fn equivalent(&self, a: Program, b: Program) -> Result<equivalence::Equivalence, Error> {
+        equivalence::equivalent(a, b, self.options.max_steps.unwrap_or(EQUIV_STEPS))
+    }
+    // Run the configured stage on input, returning what it prints
+    pub fn run(&mut self, input: &str) -> Result<String, Vec<Error>> {
+        if self.options.stage == Stage::Lex {
+            return self.lex(input).map(|tokens| printing::token_stream_to_string(&tokens)).map_err(|e| vec![e])
+        }
+        let prog = self.parse(input)?;
+        self.run_program(prog).map_err(|e| vec![e])
+    }
+    fn run_program(&mut self, prog: Program) -> Result<String, Error> {
+        let line = |e: &Expression| printing::grouped_expression_to_string(e, false) + "\n";
+        Ok(match self.options.stage {
+            Stage::Lex => unreachable!("tokens are printed before parsing"),
+            Stage::Ast => printing::program_to_string(&prog),
+            Stage::Group => printing::group_to_string(&prog),
+            Stage::Types => {
+                // Principal type of each statement and the type of the body
+                let (stmts, body) = self.typecheck(&prog)?;
+                let mut s = String::new();
+                for (id, t) in stmts.iter() {
+                    s += &format!("{} : {}\n", id.as_deref().unwrap_or("_"), printing::scheme_to_string(t))
+                }
+                s + &format!("- : {}\n", printing::type_to_string(&body))
+            },
+            Stage::Debruijn => {
+                printing::debruijn_to_string(&debruijn::to_debruijn(&evaluator::nest(prog)), self.options.index_base) + "\n"
+            },
+            Stage::EtaReduce => line(&eta::eta_reduce(&evaluator::nest(prog))),
+            Stage::EtaExpand => line(&eta::eta_expand(&evaluator::nest(prog))),
+            // Result is already printed as the last step when tracing
+            Stage::Eval => {
+                let e = self.evaluate(prog)?;
+                if self.tracing { String::new() } else { line(&e) }
+            },
+            Stage::Normal => {
+                let e = self.normalize(prog)?;
+                if self.tracing { String::new() } else { line(&e) }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(options: Options, input: &str) -> String {
+        options.build().unwrap().run(input).unwrap()
+    }
+
+    #[test]
+    fn substitution_rejects_sharing_and_dynamic_scope() {
+        let need = Options { engine: Engine::Subst, strategy: Strategy::Need, ..Options::default() };
+        assert_eq!(need.build().err(), Some(Conflict::NeedWithSubstitution));
+        let dynamic = Options { stage: Stage::Normal, dynamic: true, ..Options::default() };
+        assert_eq!(dynamic.build().err(), Some(Conflict::DynamicWithSubstitution));
+        assert!(Options { strategy: Strategy::Need, ..Options::default() }.build().is_ok());
+    }
+
+    #[test]
+    fn stages_print_their_result() {
+        assert_eq!(run(Options::default(), "1 + 2 * 3"), "7\n");
+        assert_eq!(run(Options { noprec: true, ..Options::default() }, "1 + 2 * 3"), "9\n");
+        assert_eq!(run(Options { stage: Stage::Normal, ..Options::default() }, r"(\x y. x) a"), "(\\y. a)\n");
+        assert_eq!(run(Options { stage: Stage::Types, ..Options::default() }, r"let f = \x. x in f 1"), "f : forall a. a -> a\n- : int\n");
+    }
+
+    #[test]
+    fn recovery_reports_every_syntax_error() {
+        let mut pipeline = Options { recover: true, ..Options::default() }.build().unwrap();
+        assert_eq!(pipeline.run("(1 + ) + (2 * )").unwrap_err().len(), 2);
+    }
+
+    #[test]
+    fn system_f_programs_are_checked_before_evaluating() {
+        let options = Options { systemf: true, ..Options::default() };
+        assert_eq!(run(options.clone(), r"(\x: int. x + 1) 2"), "3\n");
+        assert!(options.build().unwrap().run(r"(\x: bool. x + 1) 2").is_err());
+    }
+}
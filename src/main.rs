@@ -1,65 +1,92 @@
-mod types;
-mod lexer;
-mod printing;
-mod parser;
-mod evaluator;
-
-use clap::Parser;
-use std::io;
+mod repl;
+
+use clap::{CommandFactory, Parser, error::ErrorKind};
+use ltk::{diagnostic, error::Error, evaluator, printing, Conflict, Options, Stage};
+use std::io::{self, IsTerminal};
 use std::fs;
 use std::process;
+use std::time::Duration;
 
 // Macros
 
-macro_rules! lex {
-    ($e1: expr, $e2: expr) => {
-        match $e1.generate($e2) {
+macro_rules! read {
+    ($p: expr) => {
+        match fs::read_to_string($p) {
             Ok(s) => s,
-            Err(e) => {
-                eprintln!("{}", e);
+            Err(_) => {
+                eprintln!("file error"); 
                 process::exit(1)
             }
         }
     }
 }
 
-macro_rules! parse {
-    ($e1: expr, $e2: expr) => {
-        match $e1.parse_program($e2) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("{}", e);
-                process::exit(1)
-            }
-        }
-    }
-}
+// Argument parser
 
-macro_rules! eval {
-    ($e1: expr, $e2: expr, $e3: expr) => {
-        match $e1.eval_program($e2, $e3) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("{}", e);
-                process::exit(1)
-            }
-        }
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    #[command(about = "Check whether two programs are alpha or beta-eta equivalent")]
+    Equiv {
+        #[arg(help="Path to first program file")]
+        a: String,
+
+        #[arg(help="Path to second program file")]
+        b: String
     }
 }
 
-// Argument parser
-
 #[derive(clap::ValueEnum, Clone, Debug)]
 enum Program {
     Lex,
     Ast,
     Group,
-    Eval
+    Types,
+    Debruijn,
+    EtaReduce,
+    EtaExpand,
+    Eval,
+    Normal,
+    Repl
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum Calculus {
+    Untyped,
+    Systemf
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum Strategy {
+    Value,
+    Name,
+    Need
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum Engine {
+    Env,
+    Subst
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum ErrorFormat {
+    Human,
+    Json
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum Color {
+    Auto,
+    Always,
+    Never
 }
 
 #[derive(Parser, Debug)]
 #[command(version, about = "Lambda calculus evaluator", long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(value_enum, long("prog"), default_value_t=Program::Eval, help="Select a part of the program to run")]
     program: Program,
 
@@ -69,28 +96,179 @@ struct Args {
     #[arg(long("no-prec"), help="Disable precedence rules for binary operators")]
     noprec: bool,
 
+    #[arg(long("index-base"), default_value_t=0, value_parser=clap::value_parser!(u8).range(0..=1), help="Number de Bruijn indices from 0 or 1")]
+    index_base: u8,
+
+    #[arg(value_enum, long, default_value_t=Calculus::Untyped, help="Select the language of programs, System F annotates parameters and let rec names and instantiates nil as in 1 :: (nil [int])")]
+    calculus: Calculus,
+
+    #[arg(long, help="Check types before evaluating")]
+    typecheck: bool,
+
     #[arg(long, help="Use dynamic scoping rules in the evaluator")]
     dynamic: bool,
 
+    #[arg(value_enum, long, default_value_t=Strategy::Value, help="Select when function arguments are evaluated")]
+    strategy: Strategy,
+
+    #[arg(value_enum, long, default_value_t=Engine::Env, help="Evaluate with environments and closures or by substitution")]
+    engine: Engine,
+
+    #[arg(long, help="Eta reduce functions when normalising")]
+    eta: bool,
+
+    #[arg(long, help="Print every reduction step of the evaluator")]
+    trace: bool,
+
+    #[arg(long("max-steps"), value_name="STEPS", help="Stop evaluation after a number of reduction steps")]
+    max_steps: Option<usize>,
+
+    #[arg(long, value_name="SECONDS", value_parser=parse_timeout, help="Stop evaluation after a number of seconds")]
+    timeout: Option<Duration>,
+
+    #[arg(long, help="Recover from syntax errors to report all of them")]
+    recover: bool,
+
+    #[arg(value_enum, long("error-format"), default_value_t=ErrorFormat::Human, help="Select how errors are reported")]
+    error_format: ErrorFormat,
+
+    #[arg(value_enum, long, default_value_t=Color::Auto, help="Select when to color error output")]
+    color: Color,
+
     #[arg(help="Optional path to program file. Use stdin if not specified.")]
     fname: Option<String>
 }
 
+// Timeout in seconds, a non-negative number small enough to be a duration
+fn parse_timeout(s: &str) -> Result<Duration, String> {
+    let secs: f64 = s.parse().map_err(|_| format!("'{}' is not a number", s))?;
+    if secs.is_nan() || secs < 0.0 { return Err(format!("'{}' is not a non-negative number of seconds", s)) }
+    // Non-negative but beyond what a duration can hold
+    Duration::try_from_secs_f64(secs).map_err(|_| format!("'{}' seconds is too large for a timeout", s))
+}
+
+// Stack of the thread running the program, room for the evaluator's default depth limit
+const STACK_SIZE: usize = 512 * 1024 * 1024;
+
 fn main() {
+    // Terms are reduced recursively, so run on a thread with a stack large enough for deeply nested terms
+    let child = std::thread::Builder::new().stack_size(STACK_SIZE).spawn(run).unwrap();
+    if let Err(e) = child.join() { std::panic::resume_unwind(e) }
+}
+
+fn run() {
     // Parse CLI arguments
     let args = Args::parse();
 
-    // Get program input
-    let input: String = match args.fname {
-        Some(p) => {
-            match fs::read_to_string(p) {
-                Ok(s) => s,
-                Err(_) => {
-                    eprintln!("file error"); 
+    // Color errors if requested or if printing to a terminal
+    let color = match args.color {
+        Color::Auto => io::stderr().is_terminal(),
+        Color::Always => true,
+        Color::Never => false
+    };
+
+    // Interactive input extends an environment, which substitution does not keep
+    if let (Program::Repl, Engine::Subst) = (&args.program, &args.engine) {
+        Args::command().error(ErrorKind::ArgumentConflict, "'--engine subst' cannot be used with '--prog repl'").exit()
+    }
+    // Pipeline for the selected part of the program, the REPL evaluates its input
+    let options = Options {
+        stage: match args.program {
+            Program::Lex => Stage::Lex,
+            Program::Ast => Stage::Ast,
+            Program::Group => Stage::Group,
+            Program::Types => Stage::Types,
+            Program::Debruijn => Stage::Debruijn,
+            Program::EtaReduce => Stage::EtaReduce,
+            Program::EtaExpand => Stage::EtaExpand,
+            Program::Eval | Program::Repl => Stage::Eval,
+            Program::Normal => Stage::Normal
+        },
+        noprec: args.noprec,
+        right: args.right,
+        systemf: matches!(args.calculus, Calculus::Systemf),
+        typecheck: args.typecheck,
+        dynamic: args.dynamic,
+        strategy: match args.strategy {
+            Strategy::Value => evaluator::Strategy::Value,
+            Strategy::Name => evaluator::Strategy::Name,
+            Strategy::Need => evaluator::Strategy::Need
+        },
+        engine: match args.engine {
+            Engine::Env => evaluator::Engine::Env,
+            Engine::Subst => evaluator::Engine::Subst
+        },
+        eta: args.eta,
+        max_steps: args.max_steps,
+        timeout: args.timeout,
+        recover: args.recover,
+        index_base: args.index_base as usize
+    };
+    let mut pipeline = match options.build() {
+        Ok(p) => p,
+        Err(Conflict::NeedWithSubstitution) => {
+            Args::command().error(ErrorKind::ArgumentConflict, "'--strategy need' cannot be used with '--engine subst' or '--prog normal'").exit()
+        },
+        Err(Conflict::DynamicWithSubstitution) => {
+            Args::command().error(ErrorKind::ArgumentConflict, "'--dynamic' cannot be used with '--engine subst' or '--prog normal'").exit()
+        }
+    };
+    // Print each step as it happens
+    if args.trace {
+        pipeline.set_tracer(Some(Box::new(|step| print!("{}", printing::trace_step_to_string(step)))));
+    }
+
+    // Run interactive session, loading the program file first if specified
+    if let Program::Repl = args.program {
+        let mut session = repl::Repl::new(pipeline, color);
+        if let Some(p) = &args.fname { session.load(p) }
+        session.run();
+        return
+    }
+
+    // Compare two programs and exit with success only if they are equivalent
+    if let Some(Command::Equiv { a, b }) = &args.command {
+        let mut terms = Vec::new();
+        let mut normals = Vec::new();
+        for p in [a, b] {
+            let input = read!(p);
+            // Report errors against the source of this program
+            let normal = pipeline.parse(&input).and_then(|prog| {
+                terms.push(evaluator::nest(prog.clone()));
+                pipeline.normal_form(prog).map_err(|e| vec![e])
+            });
+            match normal {
+                Ok(n) => normals.push(n),
+                Err(errors) => {
+                    for e in errors.iter() { report(e, &input, p, &args.error_format, color) }
                     process::exit(1)
                 }
             }
-        },
+        }
+        let nb = normals.pop().unwrap();
+        let na = normals.pop().unwrap();
+        let result = ltk::equivalence::compare(&terms[0], &terms[1], na, nb);
+        println!("alpha-equivalent: {}", if result.alpha { "yes" } else { "no" });
+        match result.beta_eta {
+            Some(true) => println!("beta-eta-equivalent: yes"),
+            Some(false) => println!("beta-eta-equivalent: no"),
+            None => println!("beta-eta-equivalent: unknown (no normal form within {} steps)", args.max_steps.unwrap_or(ltk::EQUIV_STEPS))
+        }
+        // Show normal forms when they differ
+        if result.beta_eta != Some(true) {
+            for (p, n) in [(a, &result.normal.0), (b, &result.normal.1)] {
+                match n {
+                    Some(e) => println!("normal form of {}: {}", p, printing::grouped_expression_to_string(e, false)),
+                    None => println!("normal form of {}: not reached", p)
+                }
+            }
+        }
+        process::exit(if result.beta_eta == Some(true) { 0 } else { 1 })
+    }
+
+    // Get program input
+    let input: String = match &args.fname {
+        Some(p) => read!(p),
         None => {
             match io::read_to_string(io::stdin()) {
                 Ok(s) => s,
@@ -102,51 +280,21 @@ fn main() {
         }
     };
 
-    // Create lexer
-    let mut lex = lexer::Lexer::new();
-    // Create parser
-    let mut parse = parser::Parser::new(args.noprec, args.right);
-    // Create evaluator
-    let mut evaluate = evaluator::Evaluator::new();
-
-    // Run lexer program
-    match args.program{
-        Program::Lex => {
-            // Generate lexer output
-            let lexer_out = lex!(lex, &input);
-            // Print token stream
-            printing::print_token_stream(&lexer_out)
-        },
-        // Run parser program
-        Program::Ast => {
-            // Generate lexer output
-            let lexer_out = lex!(lex, &input);
-            // Generate parser output
-            let parser_out = parse!(parse, lexer_out);
-            // Print abstract syntax tree
-            printing::print_program(&parser_out);
-        },
-        // Run grouping program
-        Program::Group => {
-            // Generate lexer output
-            let lexer_out = lex!(lex, &input);
-            // Generate parser output
-            let parser_out = parse!(parse, lexer_out);
-            // Print grouping
-            printing::print_group(&parser_out);
-        },
-        // Run evaluator program
-        Program::Eval => {
-            // Generate lexer output
-            let lexer_out = lex!(lex, &input);
-            // Generate parser output
-            let parser_out = parse!(parse, lexer_out);
-            // Evaluate parser output
-            let eval_out = eval!(evaluate, parser_out, args.dynamic);
-            // Print grouping
-            printing::print_grouped_expression(&eval_out, false);
-            // Print newline
-            println!()
+    // Run the selected part of the program, reporting errors against its source
+    match pipeline.run(&input) {
+        Ok(out) => print!("{}", out),
+        Err(errors) => {
+            let fname = args.fname.as_deref().unwrap_or("<stdin>");
+            for e in errors.iter() { report(e, &input, fname, &args.error_format, color) }
+            process::exit(1)
         }
     }
-}
\ No newline at end of file
+}
+
+// Print an error against the source it was found in
+fn report(e: &Error, source: &str, fname: &str, format: &ErrorFormat, color: bool) {
+    match format {
+        ErrorFormat::Human => eprint!("{}", diagnostic::render(e, source, fname, color)),
+        ErrorFormat::Json => eprintln!("{}", diagnostic::render_json(e, fname))
+    }
+}
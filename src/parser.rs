@@ -1,9 +1,11 @@
-use crate::types::{ast::{Bop, Expression, Program, Statement, Uop, Value, Ident}, token::{Token, TokenValue, Variant}};
+use std::rc::Rc;
+use crate::types::{ast::{Bop, Case, DataType, Expression, Pattern, Program, Statement, Type, Uop, Value, Ident}, token::{Position, Token, TokenValue, Variant}};
+use crate::error::Error;
 
 // Macros
 macro_rules! token_value {
     ($e:expr, $i:path) => {
-        match $e.1 { $i(x) => x, _ => return Err("Token value error".to_string()) }
+        match $e.1 { $i(x) => x, _ => return Err(Error::SyntaxError(vec![$e.0.clone()], $e.0, None, $e.2)) }
     }
 }
 
@@ -31,6 +33,10 @@ const INEQUALITY: [(Variant, Bop); 4] = [
     (Variant::Lte, Bop::LteBop),
 ];
 
+const LIST: [(Variant, Bop); 1] = [
+    (Variant::DoubleColon, Bop::ConsBop)
+];
+
 const ARITHMETIC_LOW: [(Variant, Bop); 2] = [
     (Variant::Plus, Bop::PlusBop),
     (Variant::Minus, Bop::MinusBop),
@@ -41,7 +47,7 @@ const ARITHMETIC_HIGH: [(Variant, Bop); 2] = [
     (Variant::Div, Bop::DivBop)
 ];
 
-const OP_ALL: [(Variant, Bop); 12] = [
+const OP_ALL: [(Variant, Bop); 13] = [
     (Variant::Or, Bop::OrBop),
     (Variant::Xor, Bop::XorBop),
     (Variant::And, Bop::AndBop),
@@ -50,17 +56,75 @@ const OP_ALL: [(Variant, Bop); 12] = [
     (Variant::Gte, Bop::GteBop),
     (Variant::Lt, Bop::LtBop),
     (Variant::Lte, Bop::LteBop),
+    (Variant::DoubleColon, Bop::ConsBop),
     (Variant::Plus, Bop::PlusBop),
     (Variant::Minus, Bop::MinusBop),
     (Variant::Times, Bop::TimesBop),
     (Variant::Div, Bop::DivBop)
 ];
 
+// Parameters and their annotations
+type Parameters = (Vec<Pattern>, Vec<Option<Type>>);
+
+// Data type declared by a statement, which binds no value and scopes over the rest of the program
+pub fn declared(s: &Statement) -> Option<&Rc<DataType>> {
+    match s {
+        (Pattern::Var(None), Expression::DeclExpr(d, _)) => Some(d),
+        _ => None
+    }
+}
+
+// Function a keyword applies when it stands alone, with the type variables and parameter types it has in System F
+fn keyword_function(keyword: &Variant) -> (Vec<&'static str>, Vec<(&'static str, Type)>, Expression) {
+    let var = |x: &str| Box::new(Expression::ValExpr(Value::Identifier(x.to_string())));
+    let named = |a: &str| Type::Named(a.to_string());
+    let list = Type::List(Box::new(named("a")));
+    match keyword {
+        Variant::Fst | Variant::Snd => {
+            let i = if *keyword == Variant::Fst { 0 } else { 1 };
+            (vec!["a", "b"], vec![("p", Type::Tuple(vec![named("a"), named("b")]))], Expression::ProjExpr(var("p"), i, Some(2)))
+        },
+        Variant::Cons => (vec!["a"], vec![("h", named("a")), ("t", list)], Expression::BopExpr(Bop::ConsBop, var("h"), var("t"))),
+        Variant::Head => (vec!["a"], vec![("l", list)], Expression::UopExpr(Uop::HeadUop, var("l"))),
+        Variant::Tail => (vec!["a"], vec![("l", list)], Expression::UopExpr(Uop::TailUop, var("l"))),
+        _ => (vec!["a"], vec![("l", list)], Expression::UopExpr(Uop::IsNilUop, var("l")))
+    }
+}
+
+// Replace the placeholder for a data type within its own declaration with the type applied to its parameters
+fn uniform(t: &Type, name: &str, params: &[String]) -> Type {
+    match t {
+        Type::Data(n, tlist) if n == name && tlist.is_empty() => Type::Data(n.clone(), params.iter().map(|p| Type::Named(p.clone())).collect()),
+        Type::Data(n, tlist) => Type::Data(n.clone(), tlist.iter().map(|t1| uniform(t1, name, params)).collect()),
+        Type::Arrow(t1, t2) => Type::Arrow(Box::new(uniform(t1, name, params)), Box::new(uniform(t2, name, params))),
+        Type::Tuple(tlist) => Type::Tuple(tlist.iter().map(|t1| uniform(t1, name, params)).collect()),
+        Type::List(t1) => Type::List(Box::new(uniform(t1, name, params))),
+        Type::Forall(a, t1) => Type::Forall(a.clone(), Box::new(uniform(t1, name, params))),
+        _ => t.clone()
+    }
+}
+
 // Parser
 pub struct Parser {
     noprec: bool,
     right: bool,
+    // Explicit type abstraction and application, with type variables in scope
+    systemf: bool,
+    type_vars: Vec<String>,
+    // Declared data types in scope, innermost last
+    data_types: Vec<Rc<DataType>>,
+    // Data type being declared and the parameters found so far
+    declaring: Option<(String, Vec<String>)>,
+    // Parsing the body of a match case, where a bar starts the next case
+    in_case: bool,
     pos: usize,
+    // Furthest failure: token index, expected variants, and where they were expected
+    fail_pos: usize,
+    fail_expected: Vec<Variant>,
+    fail_context: Option<&'static str>,
+    // Recovery mode and errors recorded while recovering
+    recover: bool,
+    errors: Vec<Error>,
     tokens: Vec<Token>
 }
 impl Parser {
@@ -69,14 +133,28 @@ impl Parser {
         Parser {
             noprec,
             right,
+            systemf: false,
+            type_vars: Vec::new(),
+            data_types: Vec::new(),
+            declaring: None,
+            in_case: false,
             pos: 0,
+            fail_pos: 0,
+            fail_expected: Vec::new(),
+            fail_context: None,
+            recover: false,
+            errors: Vec::new(),
             tokens: Vec::new()
         }
     }
+    // Accept the grammar of System F
+    pub fn set_systemf(&mut self, systemf: bool) {
+        self.systemf = systemf
+    }
     fn mark(&self) -> usize {
         self.pos
     }
-    fn reset(&mut self, pos: usize) -> () {
+    fn reset(&mut self, pos: usize) {
         self.pos = pos
     }
     fn get_token(&mut self) -> Token {
@@ -85,31 +163,147 @@ impl Parser {
         token
     }
     fn peek_token(&self) -> Token {
-        if self.pos >= self.tokens.len() { return self.tokens.get(self.tokens.len() - 1).unwrap().clone() }
+        if self.pos >= self.tokens.len() { return self.tokens.last().unwrap().clone() }
         self.tokens.get(self.pos).unwrap().clone()
     }
-    fn expect(&mut self, arg: Variant) -> Result<Token, String> {
+    // Number of data types declared so far, which later input can use
+    pub fn declarations(&self) -> usize {
+        self.data_types.len()
+    }
+    // Forget data types declared after the first n
+    pub fn forget_declarations(&mut self, n: usize) {
+        self.data_types.truncate(n)
+    }
+    // Check if the last parse failed at the end of input, i.e. the input may be incomplete
+    pub fn exhausted(&self) -> bool {
+        self.fail_pos + 1 >= self.tokens.len()
+    }
+    fn expect(&mut self, arg: Variant) -> Result<Token, Error> {
+        self.expect_token(arg, None)
+    }
+    // Expect with a description of where the token was expected
+    fn expect_after(&mut self, arg: Variant, context: &'static str) -> Result<Token, Error> {
+        self.expect_token(arg, Some(context))
+    }
+    fn expect_token(&mut self, arg: Variant, context: Option<&'static str>) -> Result<Token, Error> {
         let token = self.peek_token();
         if token.0 == arg {
             Ok(self.get_token())
         } else {
-            Err(self.err_msg())
+            Err(self.fail(vec![arg], context, self.pos))
+        }
+    }
+    // Clear failure tracking before a parse
+    fn clear_failure(&mut self) {
+        self.fail_pos = 0;
+        self.fail_expected.clear();
+        self.fail_context = None;
+    }
+    // Record variants expected at a token index, generate error for the furthest failure so far
+    fn fail(&mut self, expected: Vec<Variant>, context: Option<&'static str>, index: usize) -> Error {
+        let index = index.min(self.tokens.len() - 1);
+        // Further than previous failures, start a new expected set
+        if index > self.fail_pos || self.fail_expected.is_empty() {
+            self.fail_pos = index;
+            self.fail_expected.clear();
+            self.fail_context = None;
         }
+        // Merge into expected set at the furthest position
+        if index == self.fail_pos {
+            for v in expected {
+                if !self.fail_expected.contains(&v) { self.fail_expected.push(v) }
+            }
+            self.fail_expected.sort();
+            if self.fail_context.is_none() { self.fail_context = context }
+        }
+        // Report furthest failure
+        let token = &self.tokens[self.fail_pos];
+        Error::SyntaxError(self.fail_expected.clone(), token.0.clone(), self.fail_context, token.2)
+    }
+    // Record error, then skip from the failure to a synchronising token
+    fn record(&mut self, err: Error) {
+        // Continue from furthest failure
+        self.reset(self.fail_pos);
+        self.clear_failure();
+        // Skip errors already reported at the same position
+        if !self.errors.iter().any(|e| e.span() == err.span()) { self.errors.push(err) }
+        self.synchronise()
+    }
+    // Skip tokens until let, in, unmatched rparen, or EOF
+    fn synchronise(&mut self) {
+        let mut depth: usize = 0;
+        loop {
+            match self.peek_token().0 {
+                Variant::EOF => break,
+                Variant::Let | Variant::In | Variant::RParen if depth == 0 => break,
+                Variant::LParen => depth += 1,
+                Variant::RParen => depth -= 1,
+                _ => ()
+            }
+            self.get_token();
+        }
+    }
+    // In recovery mode, replace a failed parse with an error node
+    fn recovering(&mut self, start: Position, result: Result<Expression, Error>) -> Result<Expression, Error> {
+        match result {
+            Err(err) if self.recover => {
+                self.record(err);
+                Ok(self.spanned(start, Expression::ErrorExpr))
+            },
+            r => r
+        }
+    }
+    // Start position of the next token
+    fn start(&self) -> Position {
+        self.peek_token().2.0
     }
-    // Generate error message
-    fn err_msg(&self) -> String {
-        let t_pos = self.peek_token().2;
-        "Syntax error at ".to_string() + t_pos.0.to_string().as_ref() + ":" + t_pos.1.to_string().as_ref()
+    // End position of the last consumed token
+    fn end(&self) -> Position {
+        if self.pos == 0 { return self.peek_token().2.0 }
+        self.tokens.get((self.pos - 1).min(self.tokens.len() - 1)).unwrap().2.1
+    }
+    // Wrap expression with the span from start to the last consumed token
+    fn spanned(&self, start: Position, e: Expression) -> Expression {
+        Expression::SpanExpr((start, self.end()), Box::new(e))
+    }
+    // Index of the operator for a token, a bar ends the body of a match case instead
+    fn operator(&self, oplist: &[(Variant, Bop)], v: &Variant) -> Option<usize> {
+        if self.in_case && *v == Variant::Or { return None }
+        oplist.iter().position(|r| r.0 == *v)
+    }
+    // Parse without the restrictions of an enclosing match case, for expressions with their own delimiters
+    fn nested<T>(&mut self, f: fn(&mut Self) -> Result<T, Error>) -> Result<T, Error> {
+        let in_case = std::mem::replace(&mut self.in_case, false);
+        let result = f(self);
+        self.in_case = in_case;
+        result
+    }
+    // Constructor with a name, innermost declaration first
+    fn constructor(&self, name: &str) -> Option<(Rc<DataType>, usize)> {
+        self.data_types.iter().rev().find_map(|d| d.constructors.iter().position(|c| c.0 == name).map(|i| (d.clone(), i)))
+    }
+    // Constructor named by the next token
+    fn next_constructor(&self) -> Option<(Rc<DataType>, usize)> {
+        match self.peek_token() {
+            (Variant::Ident, TokenValue::Str(s), _) => self.constructor(&s),
+            _ => None
+        }
+    }
+    // Data type with a name, innermost declaration first
+    fn data_type(&self, name: &str) -> Option<Rc<DataType>> {
+        self.data_types.iter().rev().find(|d| d.name == name).cloned()
     }
     // Parse binary operators
-    fn parse_bops(&mut self, oplist: &[(Variant, Bop)], f1: fn(&mut Self) -> Result<Expression, String>, f2: fn(&mut Self) -> Result<Expression, String>) -> Result<Expression, String> {
+    fn parse_bops(&mut self, oplist: &[(Variant, Bop)], f1: fn(&mut Self) -> Result<Expression, Error>, f2: fn(&mut Self) -> Result<Expression, Error>) -> Result<Expression, Error> {
         if self.right {
             self.parse_bops_right(oplist, f1, f2)
         } else {
             self.parse_bops_left(oplist, f1)
         }
     }
-    fn parse_bops_left(&mut self, oplist: &[(Variant, Bop)], f: fn(&mut Self) -> Result<Expression, String>) -> Result<Expression, String> {
+    fn parse_bops_left(&mut self, oplist: &[(Variant, Bop)], f: fn(&mut Self) -> Result<Expression, Error>) -> Result<Expression, Error> {
+        // Start of expression
+        let start = self.start();
         // Parse
         let head = f(self)?;
         // List of expressions
@@ -121,10 +315,11 @@ impl Parser {
             // Pop token variant
             let peek_var = self.get_token().0;
             // Parse expression after and symbol if exists, otherwise if no and symbol break
-            match oplist.iter().position(|r| r.0 == peek_var) {
+            match self.operator(oplist, &peek_var) {
                 Some(i) => {
-                    // Push operator and following expression
-                    expr_list.push((oplist[i].1.clone(), f(self)?));
+                    // Push operator, following expression, and its end
+                    let e = f(self)?;
+                    expr_list.push((oplist[i].1.clone(), e, self.end()));
                 },
                 None => {
                     self.reset(pos);
@@ -133,7 +328,7 @@ impl Parser {
             }
         };
         // If nothing, return first item
-        if expr_list.len() <= 0 {
+        if expr_list.is_empty() {
             Ok(head)
         }
         // If found exprs, glue together as left associative
@@ -145,12 +340,14 @@ impl Parser {
                     // Fold left to apply in leftmost manner
                     .fold(
                         head, 
-                        |acc, val| Expression::BopExpr(val.0, Box::new(acc), Box::new(val.1))
+                        |acc, val| Expression::SpanExpr((start, val.2), Box::new(Expression::BopExpr(val.0, Box::new(acc), Box::new(val.1))))
                     )
             )
         }
     }
-    fn parse_bops_right(&mut self, oplist: &[(Variant, Bop)], f1: fn(&mut Self) -> Result<Expression, String>, f2: fn(&mut Self) -> Result<Expression, String>) -> Result<Expression, String> {
+    fn parse_bops_right(&mut self, oplist: &[(Variant, Bop)], f1: fn(&mut Self) -> Result<Expression, Error>, f2: fn(&mut Self) -> Result<Expression, Error>) -> Result<Expression, Error> {
+        // Start of expression
+        let start = self.start();
         // Parse
         let head = f1(self)?;
         // Mark current position
@@ -158,10 +355,11 @@ impl Parser {
         // Pop next token
         let peek_var = self.get_token().0;
         // Is it in the operators to look for?
-        match oplist.iter().position(|r| r.0 == peek_var) {
+        match self.operator(oplist, &peek_var) {
             Some(i) => {
                 // Generate expression
-                Ok(Expression::BopExpr(oplist[i].1.clone(), Box::new(head), Box::new(f2(self)?)))
+                let e = Expression::BopExpr(oplist[i].1.clone(), Box::new(head), Box::new(f2(self)?));
+                Ok(self.spanned(start, e))
             },
             None => {
                 self.reset(pos);
@@ -170,25 +368,78 @@ impl Parser {
         }
     }
     // Parse
-    pub fn parse_program(&mut self, tokens: Vec<Token>) -> Result<Program, String> {
+    pub fn parse_program(&mut self, tokens: Vec<Token>) -> Result<Program, Error> {
         // Set up parse
         self.tokens = tokens;
         self.reset(0);
+        self.clear_failure();
+        self.data_types.clear();
         // Parse
         self.program()
     }
-    // Parsing rules
-    fn program(&mut self) -> Result<Program, String> {
+    // Parse, recovering from syntax errors to report all of them
+    pub fn parse_program_recover(&mut self, tokens: Vec<Token>) -> (Program, Vec<Error>) {
+        // Set up parse
+        self.tokens = tokens;
+        self.reset(0);
+        self.clear_failure();
+        self.data_types.clear();
+        self.errors.clear();
+        self.recover = true;
+        // Parse, cannot fail in recovery mode
+        let prog = self.program();
+        self.recover = false;
+        // Errors in source order
+        let mut errors = std::mem::take(&mut self.errors);
+        errors.sort_by_key(|e| e.span().0);
+        (prog.unwrap_or((Vec::new(), Expression::ErrorExpr)), errors)
+    }
+    // Parse interactive input, where the trailing expression is optional
+    pub fn parse_repl(&mut self, tokens: Vec<Token>) -> Result<(Vec<Statement>, Option<Expression>), Error> {
+        // Set up parse
+        self.tokens = tokens;
+        self.reset(0);
+        self.clear_failure();
         // Statement list
         let slist = self.stmtlist()?;
+        // Statements only
+        if self.peek_token().0 == Variant::EOF { return Ok((slist, None)) }
         // Expression
         let e = self.expression()?;
         // Expect EOF
         self.expect(Variant::EOF)?;
         // Put together
+        Ok((slist, Some(e)))
+    }
+    // Parsing rules
+    fn program(&mut self) -> Result<Program, Error> {
+        // Statement list
+        let slist = self.stmtlist()?;
+        // Expression
+        let start = self.start();
+        let result = self.expression();
+        let e = self.recovering(start, result)?;
+        // Expect EOF
+        while let Err(err) = self.expect(Variant::EOF) {
+            if !self.recover { return Err(err) }
+            let pos = self.mark();
+            self.record(err);
+            // Stray closing tokens are part of the same error
+            while matches!(self.peek_token().0, Variant::RParen | Variant::In) { self.get_token(); }
+            // Always make progress past the stray token
+            if self.mark() == pos && self.peek_token().0 != Variant::Let { self.get_token(); }
+            // Rest of the input is only checked for errors, an operator continues the expression before the stray tokens
+            if self.operator(&OP_ALL, &self.peek_token().0).is_some() { self.get_token(); }
+            self.stmtlist()?;
+            if self.peek_token().0 == Variant::EOF { continue }
+            let start = self.start();
+            let result = self.expression();
+            self.recovering(start, result)?;
+        }
+        // Put together
         Ok((slist, e))
     }
-    fn stmtlist(&mut self) -> Result<Vec<Statement>, String> {
+    fn stmtlist(&mut self) -> Result<Vec<Statement>, Error> {
         // Empty statement vector
         let mut stmt_vec = Vec::new();
         // Consume statements
@@ -198,7 +449,7 @@ impl Parser {
             // Consume statement
             match self.statement() {
                 Ok(s) => {
-                    stmt_vec.push(s)
+                    stmt_vec.extend(s)
                 },
                 Err(_) => {
                     self.reset(pos);
@@ -209,21 +460,101 @@ impl Parser {
         // Return list of statements
         Ok(stmt_vec)
     }
-    fn statement(&mut self) -> Result<Statement, String> {
+    fn statement(&mut self) -> Result<Vec<Statement>, Error> {
+        // Start of statement
+        let start = self.start();
+        // Type declaration binds no value, its constructors stay in scope
+        if self.expect(Variant::Type).is_ok() {
+            let n = self.data_types.len();
+            return match self.declaration() {
+                Ok(d) => Ok(vec![(Pattern::Var(None), Expression::DeclExpr(d, Box::new(Expression::ValExpr(Value::Unit))))]),
+                Err(err) => {
+                    self.data_types.truncate(n);
+                    Err(err)
+                }
+            }
+        }
         // Expect a let keyword
         self.expect(Variant::Let)?;
+        // Committed to a statement after let keyword
+        match self.statement_body() {
+            Err(err) if self.recover => {
+                self.record(err);
+                // Unmatched rparen closes nothing in a statement, skip it and continue to the in keyword
+                while self.peek_token().0 == Variant::RParen {
+                    self.get_token();
+                    self.synchronise();
+                }
+                // Consume in keyword ending the statement
+                if self.peek_token().0 == Variant::In { self.get_token(); }
+                Ok(vec![(Pattern::Var(None), self.spanned(start, Expression::ErrorExpr))])
+            },
+            r => r
+        }
+    }
+    fn statement_body(&mut self) -> Result<Vec<Statement>, Error> {
+        // Parse bindings, recursive if let is followed by rec keyword
+        let bindings = if self.expect(Variant::Rec).is_ok() {
+            self.rec_bindings()?
+        } else {
+            self.binding()?
+        };
+        // Expect an in keyword
+        self.expect_after(Variant::In, "in `let` binding")?;
+        // Return
+        Ok(bindings)
+    }
+    fn binding(&mut self) -> Result<Vec<Statement>, Error> {
+        // Expect an identifier or a tuple pattern
+        let pattern = self.pattern()?;
+        // Expect an equal sign
+        self.expect_after(Variant::Eq, "in `let` binding")?;
+        // Parse an expression
+        let e = self.expression()?;
+        // Put together
+        Ok(vec![(pattern, e)])
+    }
+    fn named_binding(&mut self) -> Result<(Ident, Option<Type>, Expression), Error> {
         // Expect an identifier
         let id = self.identifier()?;
+        // Type of the recursive function, which System F cannot infer
+        let t = if self.systemf {
+            self.expect_after(Variant::Colon, "after recursive name, System F needs its type")?;
+            Some(self.type_expr()?)
+        } else {
+            self.annotation()?
+        };
         // Expect an equal sign
-        self.expect(Variant::Eq)?;
+        self.expect_after(Variant::Eq, "in `let` binding")?;
         // Parse an expression
         let e = self.expression()?;
-        // Expect an in keyword
-        self.expect(Variant::In)?;
         // Put together
-        Ok((id, e))
+        Ok((id, t, e))
+    }
+    fn rec_bindings(&mut self) -> Result<Vec<Statement>, Error> {
+        // Bindings separated by and keywords, with the start of each
+        let mut bindings = Vec::new();
+        loop {
+            let start = self.start();
+            bindings.push((start, self.named_binding()?, self.end()));
+            if self.expect(Variant::RecAnd).is_err() { break }
+        };
+        // Names bound by the group
+        let names: Vec<Pattern> = bindings.iter().map(|b| Pattern::Var(b.1.0.clone())).collect();
+        let types: Vec<Option<Type>> = bindings.iter().map(|b| b.1.1.clone()).collect();
+        // Each definition becomes a function of every name in the group, annotated with their types
+        let funcs: Vec<Expression> = bindings.iter()
+            .map(|b| Expression::FuncExpr(names.clone(), types.clone(), Box::new(b.1.2.clone())))
+            .collect();
+        // Bind each name to its component of the fixpoint
+        Ok(bindings.iter().enumerate().map(|(i, b)| (
+            Pattern::Var(b.1.0.clone()),
+            Expression::SpanExpr((b.0, b.2), Box::new(Expression::FixExpr(funcs.clone(), i)))
+        )).collect())
     }
-    fn expression(&mut self) -> Result<Expression, String> {
+    fn expression(&mut self) -> Result<Expression, Error> {
+        // Start of expression
+        let start = self.start();
         // Parse an e1
         let head = self.e1()?;
         // Vector of applications
@@ -231,6 +562,17 @@ impl Parser {
         // Loop and consume e1s until error
         loop {
             let pos = self.mark();
+            // Type argument applies to the chain so far, brackets that do not hold a type are a list argument
+            if self.systemf && self.expect(Variant::LBracket).is_ok() {
+                match self.type_expr().and_then(|t| self.expect_after(Variant::RBracket, "after type argument").map(|_| t)) {
+                    Ok(t) => {
+                        let e = if app_vec.len() == 1 { app_vec.pop().unwrap() } else { self.spanned(start, Expression::ApplicationExpr(app_vec)) };
+                        app_vec = vec![ self.spanned(start, Expression::TypeAppExpr(Box::new(e), t)) ];
+                        continue
+                    },
+                    Err(_) => self.reset(pos)
+                }
+            }
             match self.e1() {
                 Ok(ex) => app_vec.push(ex),
                 _ => {
@@ -243,83 +585,388 @@ impl Parser {
         if app_vec.len() == 1 {
             Ok(app_vec.pop().unwrap())
         } else {
-            Ok(Expression::ApplicationExpr(app_vec))
+            Ok(self.spanned(start, Expression::ApplicationExpr(app_vec)))
         }
     }
-    fn e1(&mut self) -> Result<Expression, String> {
+    fn e1(&mut self) -> Result<Expression, Error> {
+        // Start of expression
+        let start = self.start();
+        // Type abstraction
+        if self.systemf && self.expect(Variant::TypeLambda).is_ok() {
+            let result = self.type_function(start);
+            return self.recovering(start, result)
+        }
         // Expect a lambda character
         match self.expect(Variant::Lambda) {
-            // Found backslash
+            // Found backslash, committed to a function
             Ok(_) => {
-                // Parse an identlist
-                let ilist = self.identlist()?;
-                // Expect a dot
-                self.expect(Variant::Dot)?;
-                // Parse an expression
-                let body = self.expression()?;
-                // Return
-                Ok(Expression::FuncExpr(ilist, Box::new(body)))
+                let result = self.function(start);
+                self.recovering(start, result)
             },
-            // If error, parse binary operators
-            _ => {
-                if self.noprec {
-                    self.parse_bops(&OP_ALL, Self::e9, Self::e1)
-                } else {
-                    self.e2()
+            // If error, check for conditional
+            _ => match self.expect(Variant::If) {
+                // Found if, committed to a conditional
+                Ok(_) => {
+                    let result = self.conditional(start);
+                    self.recovering(start, result)
+                },
+                // If error, check for let expression
+                _ => match self.expect(Variant::Let) {
+                    // Found let, committed to a let expression
+                    Ok(_) => {
+                        let result = self.let_expression(start);
+                        self.recovering(start, result)
+                    },
+                    // If error, check for match expression
+                    _ => match self.expect(Variant::Match) {
+                        // Found match, committed to a match expression
+                        Ok(_) => {
+                            let result = self.match_expression(start);
+                            self.recovering(start, result)
+                        },
+                        // If error, check for type declaration
+                        _ => match self.expect(Variant::Type) {
+                            // Found type, committed to a declaration scoped to the following expression
+                            Ok(_) => {
+                                let result = self.type_expression(start);
+                                self.recovering(start, result)
+                            },
+                            // If error, parse binary operators
+                            _ => {
+                                if self.noprec {
+                                    self.parse_bops(&OP_ALL, Self::e10, Self::e1)
+                                } else {
+                                    self.e2()
+                                }
+                            }
+                        }
+                    }
                 }
             }
         }
     }
-    fn e2(&mut self) -> Result<Expression, String> {
+    fn function(&mut self, start: Position) -> Result<Expression, Error> {
+        // Parse an identlist with optional annotations
+        let (ilist, types) = self.identlist()?;
+        // Expect a dot
+        self.expect_after(Variant::Dot, "after lambda parameters")?;
+        // Parse an expression
+        let body = self.expression()?;
+        // Return
+        Ok(self.spanned(start, Expression::FuncExpr(ilist, types, Box::new(body))))
+    }
+    fn type_function(&mut self, start: Position) -> Result<Expression, Error> {
+        // Parse type variables, which are in scope in the body
+        let vars = self.type_varlist()?;
+        self.expect_after(Variant::Dot, "after type parameters")?;
+        let n = self.type_vars.len();
+        self.type_vars.extend(vars.iter().cloned());
+        let body = self.expression();
+        self.type_vars.truncate(n);
+        // One abstraction per variable
+        let e = vars.into_iter().rev().fold(body?, |acc, a| Expression::TypeFuncExpr(a, Box::new(acc)));
+        Ok(self.spanned(start, e))
+    }
+    fn type_varlist(&mut self) -> Result<Vec<String>, Error> {
+        // At least one type variable
+        let mut vars = Vec::new();
+        loop {
+            let token = match self.expect(Variant::Ident) {
+                Ok(token) => token,
+                Err(e) if vars.is_empty() => return Err(e),
+                _ => break
+            };
+            vars.push(token_value!(token, TokenValue::Str));
+        };
+        Ok(vars)
+    }
+    fn let_expression(&mut self, start: Position) -> Result<Expression, Error> {
+        // Parse bindings and in keyword, same as a statement
+        let bindings = self.statement_body()?;
+        // Parse body
+        let body = self.expression()?;
+        // Nest bindings, first binding outermost
+        let e = bindings.into_iter().rev().fold(body, |acc, (id, ex)| Expression::LetExpr(id, Box::new(ex), Box::new(acc)));
+        // Return
+        Ok(self.spanned(start, e))
+    }
+    fn match_expression(&mut self, start: Position) -> Result<Expression, Error> {
+        // Parse scrutinee
+        let e = self.nested(Self::expression)?;
+        self.expect_after(Variant::With, "in `match` expression")?;
+        // Cases separated by bars, the first may also be preceded by one
+        self.expect(Variant::Or).ok();
+        let mut cases = vec![ self.case()? ];
+        while self.expect(Variant::Or).is_ok() { cases.push(self.case()?) };
+        // Return
+        Ok(self.spanned(start, Expression::MatchExpr(Box::new(e), cases)))
+    }
+    fn case(&mut self) -> Result<Case, Error> {
+        // Constructor followed by a variable for each field, or a single variable matching anything
+        let constructor = self.next_constructor();
+        let vars = match &constructor {
+            Some((d, i)) => {
+                self.get_token();
+                (0..d.constructors[*i].1.len()).map(|_| self.identifier()).collect::<Result<_, _>>()?
+            },
+            None => vec![ self.identifier()? ]
+        };
+        self.expect_after(Variant::Arrow, "in `match` case")?;
+        // Body extends to the next bar
+        let in_case = std::mem::replace(&mut self.in_case, true);
+        let body = self.expression();
+        self.in_case = in_case;
+        Ok(Case { constructor, vars, body: body? })
+    }
+    fn type_expression(&mut self, start: Position) -> Result<Expression, Error> {
+        // Constructors are in scope in the body only
+        let n = self.data_types.len();
+        let result = self.declaration().and_then(|d| Ok(Expression::DeclExpr(d, Box::new(self.expression()?))));
+        self.data_types.truncate(n);
+        Ok(self.spanned(start, result?))
+    }
+    fn declaration(&mut self) -> Result<Rc<DataType>, Error> {
+        // Name of the data type
+        let token = self.expect_after(Variant::Ident, "in `type` declaration")?;
+        let name = token_value!(token, TokenValue::Str);
+        self.expect_after(Variant::Eq, "in `type` declaration")?;
+        // Unknown type names in fields become parameters
+        let outer = self.declaring.replace((name.clone(), Vec::new()));
+        let constructors = self.constructors();
+        let (_, params) = std::mem::replace(&mut self.declaring, outer).unwrap();
+        // The type refers to itself with its parameters unchanged
+        let constructors = constructors?.into_iter()
+            .map(|(c, fields)| (c, fields.iter().map(|t| uniform(t, &name, &params)).collect()))
+            .collect();
+        self.expect_after(Variant::In, "after `type` declaration")?;
+        let d = Rc::new(DataType { name, params, constructors });
+        self.data_types.push(d.clone());
+        Ok(d)
+    }
+    fn constructors(&mut self) -> Result<Vec<(String, Vec<Type>)>, Error> {
+        // Constructors separated by bars, the first may also be preceded by one
+        self.expect(Variant::Or).ok();
+        let mut constructors = Vec::new();
+        loop {
+            let token = self.expect_after(Variant::Ident, "in `type` declaration")?;
+            // Field types until the next bar or the end of the declaration
+            let mut fields = Vec::new();
+            while matches!(self.peek_token().0, Variant::Ident | Variant::LParen) { fields.push(self.type_atom()?) };
+            constructors.push((token_value!(token, TokenValue::Str), fields));
+            if self.expect(Variant::Or).is_err() { break }
+        };
+        Ok(constructors)
+    }
+    fn conditional(&mut self, start: Position) -> Result<Expression, Error> {
+        // Parse condition
+        let cond = self.expression()?;
+        // Expect then keyword and branch
+        self.expect_after(Variant::Then, "in `if` expression")?;
+        let e1 = self.expression()?;
+        // Expect else keyword and branch
+        self.expect_after(Variant::Else, "in `if` expression")?;
+        let e2 = self.expression()?;
+        // Return
+        Ok(self.spanned(start, Expression::IfExpr(Box::new(cond), Box::new(e1), Box::new(e2))))
+    }
+    fn e2(&mut self) -> Result<Expression, Error> {
         // Parse logical operators
         self.parse_bops(&LOGICAL_LOW, Self::e3, Self::e2)
     }
-    fn e3(&mut self) -> Result<Expression, String> {
+    fn e3(&mut self) -> Result<Expression, Error> {
         // Parse logical operators
         self.parse_bops(&LOGICAL_MEDIUM, Self::e4, Self::e3)
     }
-    fn e4(&mut self) -> Result<Expression, String> {
+    fn e4(&mut self) -> Result<Expression, Error> {
         // Parse logical operators
         self.parse_bops(&LOGICAL_HIGH, Self::e5, Self::e4)
     }
-    fn e5(&mut self) -> Result<Expression, String> {
+    fn e5(&mut self) -> Result<Expression, Error> {
         // Parse comparison operators
         self.parse_bops(&EQUALITY, Self::e6, Self::e5)
     }
-    fn e6(&mut self) -> Result<Expression, String> {
+    fn e6(&mut self) -> Result<Expression, Error> {
         // Parse comparison operators
         self.parse_bops(&INEQUALITY, Self::e7, Self::e6)
     }
-    fn e7(&mut self) -> Result<Expression, String> {
+    fn e7(&mut self) -> Result<Expression, Error> {
+        // Parse list construction, which always associates to the right
+        self.parse_bops_right(&LIST, Self::e8, Self::e7)
+    }
+    fn e8(&mut self) -> Result<Expression, Error> {
         // Parse arithmetic operators
-        self.parse_bops(&ARITHMETIC_LOW, Self::e8, Self::e7)
+        self.parse_bops(&ARITHMETIC_LOW, Self::e9, Self::e8)
     }
-    fn e8(&mut self) -> Result<Expression, String> {
+    fn e9(&mut self) -> Result<Expression, Error> {
         // Parse arithmetic operators
-        self.parse_bops(&ARITHMETIC_HIGH, Self::e9, Self::e8)
+        self.parse_bops(&ARITHMETIC_HIGH, Self::e10, Self::e9)
     }
-    fn e9(&mut self) -> Result<Expression, String> {
+    fn e10(&mut self) -> Result<Expression, Error> {
         // Mark position
         let pos = self.mark();
+        // Start of expression
+        let start = self.start();
         // Check for uops, reset if didn't find
         Ok(match self.get_token().0 {
-            Variant::Not => Expression::UopExpr(Uop::NotUop, Box::new(self.e9()?)),
-            Variant::Minus => Expression::UopExpr(Uop::NegUop, Box::new(self.e9()?)),
+            Variant::Not => {
+                let e = Expression::UopExpr(Uop::NotUop, Box::new(self.e10()?));
+                self.spanned(start, e)
+            },
+            Variant::Minus => {
+                let e = Expression::UopExpr(Uop::NegUop, Box::new(self.e10()?));
+                self.spanned(start, e)
+            },
+            Variant::Fix => {
+                let e = Expression::FixExpr(vec![self.e10()?], 0);
+                self.spanned(start, e)
+            },
+            // Pair projections, a function when standing alone
+            Variant::Fst => match self.operand()? {
+                Some(e1) => self.spanned(start, Expression::ProjExpr(Box::new(e1), 0, Some(2))),
+                None => self.keyword(start, Variant::Fst)
+            },
+            Variant::Snd => match self.operand()? {
+                Some(e1) => self.spanned(start, Expression::ProjExpr(Box::new(e1), 1, Some(2))),
+                None => self.keyword(start, Variant::Snd)
+            },
+            // List operations, functions when standing alone
+            Variant::Cons => {
+                let at = self.mark();
+                match (self.operand()?, self.operand()?) {
+                    (Some(head), Some(tail)) => self.spanned(start, Expression::BopExpr(Bop::ConsBop, Box::new(head), Box::new(tail))),
+                    // Partially applied, the application chain supplies the arguments
+                    _ => {
+                        self.reset(at);
+                        self.keyword(start, Variant::Cons)
+                    }
+                }
+            },
+            Variant::Nil => self.spanned(start, Expression::ListExpr(Vec::new())),
+            Variant::Head => match self.operand()? {
+                Some(e1) => self.spanned(start, Expression::UopExpr(Uop::HeadUop, Box::new(e1))),
+                None => self.keyword(start, Variant::Head)
+            },
+            Variant::Tail => match self.operand()? {
+                Some(e1) => self.spanned(start, Expression::UopExpr(Uop::TailUop, Box::new(e1))),
+                None => self.keyword(start, Variant::Tail)
+            },
+            Variant::IsNil => match self.operand()? {
+                Some(e1) => self.spanned(start, Expression::UopExpr(Uop::IsNilUop, Box::new(e1))),
+                None => self.keyword(start, Variant::IsNil)
+            },
+            Variant::LBracket => {
+                // Elements separated by commas, possibly none
+                let e = self.nested(Self::list)?;
+                self.spanned(start, e)
+            },
             Variant::LParen => {
-                // Parse expression
-                let e = self.expression()?;
+                // Parse expression, or components of a tuple
+                let result = self.nested(Self::tuple);
                 // Exect rparen
-                self.expect(Variant::RParen)?;
-                // Return
-                e
+                let result = result.and_then(|e| self.expect_after(Variant::RParen, "in parentheses").map(|_| e));
+                // Tuple spans its parentheses, then projections follow
+                let result = result.and_then(|e| {
+                    let e = if let Expression::TupleExpr(_) = e { self.spanned(start, e) } else { e };
+                    self.projections(start, e)
+                });
+                // Committed after lparen, in recovery mode consume rparen after error
+                match result {
+                    Err(err) if self.recover => {
+                        self.record(err);
+                        if self.peek_token().0 == Variant::RParen { self.get_token(); }
+                        self.spanned(start, Expression::ErrorExpr)
+                    },
+                    r => r?
+                }
             }
             _ => {
                 self.reset(pos);
-                Expression::ValExpr(self.value()?)
+                // Constructor applied to one argument for each field
+                if let Some((d, i)) = self.next_constructor() {
+                    self.get_token();
+                    let mut elist = Vec::new();
+                    for _ in 0..d.constructors[i].1.len() { elist.push(self.e10()?) };
+                    return Ok(self.spanned(start, Expression::ConstructExpr(d, i, elist)))
+                }
+                // Expected a value or one of the tokens checked above
+                let e = match self.value() {
+                    Ok(v) => Expression::ValExpr(v),
+                    Err(_) => return Err(self.fail(vec![
+                        Variant::Not, Variant::Minus, Variant::Fix, Variant::Fst, Variant::Snd, Variant::LParen,
+                        Variant::LBracket, Variant::Cons, Variant::Nil, Variant::Head, Variant::Tail, Variant::IsNil
+                    ], None, pos))
+                };
+                let e = self.spanned(start, e);
+                self.projections(start, e)?
             }
         })
     }
-    fn value(&mut self) -> Result<Value, String> {
+    // Operand of a keyword, none if nothing that can be one follows
+    fn operand(&mut self) -> Result<Option<Expression>, Error> {
+        let at = self.mark();
+        // Type argument applies to the keyword standing alone, as brackets holding a type do in an application chain
+        if self.systemf && self.expect(Variant::LBracket).is_ok() {
+            let is_type = self.type_expr().and_then(|_| self.expect(Variant::RBracket)).is_ok();
+            self.reset(at);
+            if is_type { return Ok(None) }
+        }
+        match self.e10() {
+            Ok(e) => Ok(Some(e)),
+            Err(_) if self.fail_pos == at => {
+                self.reset(at);
+                Ok(None)
+            },
+            Err(err) => Err(err)
+        }
+    }
+    // Keyword standing alone as the function it applies, polymorphic in System F
+    fn keyword(&mut self, start: Position, keyword: Variant) -> Expression {
+        let (vars, params, body) = keyword_function(&keyword);
+        // Curried, so it can be partially applied
+        let f = params.into_iter().rev().fold(body, |acc, (x, t)| {
+            Expression::FuncExpr(vec![Pattern::Var(Some(x.to_string()))], vec![if self.systemf { Some(t) } else { None }], Box::new(acc))
+        });
+        let e = if self.systemf { vars.into_iter().rev().fold(f, |acc, a| Expression::TypeFuncExpr(a.to_string(), Box::new(acc))) } else { f };
+        self.spanned(start, e)
+    }
+    fn tuple(&mut self) -> Result<Expression, Error> {
+        // Parse expression
+        let head = self.expression()?;
+        // Parenthesised expression unless followed by a comma
+        if self.peek_token().0 != Variant::Comma { return Ok(head) }
+        let mut elist = vec![ head ];
+        while self.expect(Variant::Comma).is_ok() { elist.push(self.expression()?) };
+        Ok(Expression::TupleExpr(elist))
+    }
+    fn list(&mut self) -> Result<Expression, Error> {
+        // Empty list
+        if self.expect(Variant::RBracket).is_ok() { return Ok(Expression::ListExpr(Vec::new())) }
+        // Elements until closing bracket
+        let mut elist = vec![ self.expression()? ];
+        while self.expect(Variant::Comma).is_ok() { elist.push(self.expression()?) };
+        self.expect_after(Variant::RBracket, "in list")?;
+        Ok(Expression::ListExpr(elist))
+    }
+    fn projections(&mut self, start: Position, e: Expression) -> Result<Expression, Error> {
+        // Dot followed by a number projects a component
+        let mut e = e;
+        loop {
+            let pos = self.mark();
+            if self.expect(Variant::Dot).is_err() { break }
+            match self.expect(Variant::Number) {
+                Ok(token) => {
+                    let i = token_value!(token, TokenValue::Number);
+                    e = self.spanned(start, Expression::ProjExpr(Box::new(e), i as usize, None));
+                },
+                Err(_) => {
+                    self.reset(pos);
+                    break
+                }
+            }
+        };
+        Ok(e)
+    }
+    fn value(&mut self) -> Result<Value, Error> {
         // Head of token list
         let token_head = self.get_token();
         // Check head of token list
@@ -328,40 +975,187 @@ impl Parser {
             Variant::Boolean => Value::Boolean(token_value!(token_head, TokenValue::Boolean)),
             Variant::Number => Value::Number(token_value!(token_head, TokenValue::Number)),
             Variant::Unit => Value::Unit,
-            _ => return Err(self.err_msg())
+            _ => return Err(self.fail(vec![Variant::Ident, Variant::Number, Variant::Boolean, Variant::Unit], None, self.pos - 1))
         })
     }
-    fn identifier(&mut self) -> Result<Ident, String> {
+    fn identifier(&mut self) -> Result<Ident, Error> {
         // Check for ident token
         match self.expect(Variant::Ident) {
-            Ok((_, val, _)) => match val {
-                TokenValue::Str(s) => Ok(Some(s)),
-                _ => Err(self.err_msg())
-            },
+            Ok(token) => Ok(Some(token_value!(token, TokenValue::Str))),
             // No ident, check for unit token
             _ => match self.expect(Variant::Unit) {
                 Ok(_) => Ok(None),
-                Err(msg) => Err(msg)
+                Err(e) => Err(e)
             }
         }
     }
-    fn identlist(&mut self) -> Result<Vec<Ident>, String> {
-        // Check for at least one identifier
-        let head = self.identifier()?;
-        // Vector of identifiers
+    fn identlist(&mut self) -> Result<Parameters, Error> {
+        // Check for at least one identifier or tuple pattern
+        let head = self.pattern()?;
+        // Vectors of patterns and their annotations
         let mut id_vec = vec![ head ];
-        // Loop and consume idents until error
+        let mut type_vec = vec![ self.annotation()? ];
+        // Loop and consume patterns until error
         loop {
             let pos = self.mark();
-            match self.identifier() {
-                Ok(s) => id_vec.push(s),
+            match self.pattern() {
+                Ok(p) => {
+                    id_vec.push(p);
+                    type_vec.push(self.annotation()?);
+                },
                 _ => {
                     self.reset(pos);
                     break
                 }
             }
         };
-        // Return vector
-        Ok(id_vec)
+        // Return vectors
+        Ok((id_vec, type_vec))
+    }
+    fn pattern(&mut self) -> Result<Pattern, Error> {
+        // Identifier
+        if self.expect(Variant::LParen).is_err() { return Ok(Pattern::Var(self.identifier()?)) }
+        // Tuple of at least two patterns
+        let mut plist = vec![ self.pattern()? ];
+        while self.expect(Variant::Comma).is_ok() { plist.push(self.pattern()?) };
+        if plist.len() == 1 { return Err(self.fail(vec![Variant::Comma], Some("in tuple pattern"), self.pos)) }
+        self.expect_after(Variant::RParen, "in tuple pattern")?;
+        Ok(Pattern::Tuple(plist))
     }
-}
\ No newline at end of file
+    fn annotation(&mut self) -> Result<Option<Type>, Error> {
+        // Annotation starts with a colon
+        if self.expect(Variant::Colon).is_err() { return Ok(None) }
+        Ok(Some(self.type_expr()?))
+    }
+    fn type_expr(&mut self) -> Result<Type, Error> {
+        // Product of argument types binds tighter than arrows
+        // Quantified type, variables in scope to the right
+        if self.systemf && matches!(self.peek_token(), (Variant::Ident, TokenValue::Str(s), _) if s == "forall") {
+            self.get_token();
+            let vars = self.type_varlist()?;
+            self.expect_after(Variant::Dot, "after `forall` variables")?;
+            let n = self.type_vars.len();
+            self.type_vars.extend(vars.iter().cloned());
+            let body = self.type_expr();
+            self.type_vars.truncate(n);
+            return Ok(vars.into_iter().rev().fold(body?, |acc, a| Type::Forall(a, Box::new(acc))))
+        }
+        // Parse argument type, a tuple type if components are separated by stars
+        let mut components = vec![ self.type_atom()? ];
+        while self.expect(Variant::Times).is_ok() { components.push(self.type_atom()?) };
+        let head = if components.len() == 1 { components.pop().unwrap() } else { Type::Tuple(components) };
+        // Arrows associate to the right
+        if self.expect(Variant::Arrow).is_ok() {
+            Ok(Type::Arrow(Box::new(head), Box::new(self.type_expr()?)))
+        } else {
+            Ok(head)
+        }
+    }
+    fn type_atom(&mut self) -> Result<Type, Error> {
+        // List types and data types of one parameter are written after the argument type
+        let mut t = self.type_base()?;
+        while let (Variant::Ident, TokenValue::Str(name), _) = self.peek_token() {
+            if name == "list" {
+                t = Type::List(Box::new(t));
+            } else if let Some(d) = self.data_type(&name).filter(|d| d.params.len() == 1) {
+                t = Type::Data(d.name.clone(), Box::new([ t ]));
+            } else {
+                break
+            }
+            self.get_token();
+        }
+        Ok(t)
+    }
+    fn type_base(&mut self) -> Result<Type, Error> {
+        // Parenthesised type, or arguments of a data type separated by commas
+        if self.expect(Variant::LParen).is_ok() {
+            let mut tlist = vec![ self.type_expr()? ];
+            while self.expect(Variant::Comma).is_ok() { tlist.push(self.type_expr()?) };
+            self.expect_after(Variant::RParen, "in type annotation")?;
+            if tlist.len() == 1 { return Ok(tlist.pop().unwrap()) }
+            let token = self.expect_after(Variant::Ident, "after type arguments")?;
+            let name = token_value!(token, TokenValue::Str);
+            return match self.data_type(&name) {
+                Some(d) if d.params.len() == tlist.len() => Ok(Type::Data(name, tlist.into())),
+                _ => Err(Error::UnknownTypeError(name, token.2))
+            }
+        }
+        // Base type name
+        let token = self.expect_after(Variant::Ident, "in type annotation")?;
+        match token_value!(token, TokenValue::Str).as_str() {
+            "int" => Ok(Type::Int),
+            "bool" => Ok(Type::Bool),
+            "unit" => Ok(Type::Unit),
+            // Data type refers to itself while being declared
+            name if self.declaring.as_ref().is_some_and(|d| d.0 == name) => Ok(Type::Data(name.to_string(), Box::new([]))),
+            // Declared data type without parameters
+            name if self.data_type(name).is_some_and(|d| d.params.is_empty()) => Ok(Type::Data(name.to_string(), Box::new([]))),
+            // Parameter of the data type being declared
+            name if self.declaring.is_some() => {
+                let params = &mut self.declaring.as_mut().unwrap().1;
+                if !params.iter().any(|p| p == name) { params.push(name.to_string()) };
+                Ok(Type::Named(name.to_string()))
+            },
+            // Type variable bound by an enclosing abstraction or forall
+            name if self.type_vars.iter().any(|a| a == name) => Ok(Type::Named(name.to_string())),
+            name => Err(Error::UnknownTypeError(name.to_string(), token.2))
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn error(src: &str) -> Error {
+        let tokens = Lexer::new().generate(src).unwrap();
+        Parser::new(false, false).parse_program(tokens).unwrap_err()
+    }
+
+    #[test]
+    fn reports_expected_token_with_its_context() {
+        let e = error("let x 1 in x");
+        assert_eq!(e.message(), "expected `=` in `let` binding; found number");
+        assert_eq!(e.span(), ((0, 6), (0, 7)));
+    }
+
+    #[test]
+    fn merges_tokens_expected_at_the_furthest_failure() {
+        let Error::SyntaxError(expected, found, _, span) = error("(1, 2 in") else { panic!("not a syntax error") };
+        assert!(expected.contains(&Variant::RParen) && expected.contains(&Variant::Comma));
+        assert_eq!((found, span.0), (Variant::In, (0, 6)));
+    }
+
+    #[test]
+    fn backtracking_keeps_the_deepest_failure() {
+        // Statement list backtracks out of the let, the error is still where the binding went wrong
+        assert_eq!(error("let x = 1 x").message(), "expected one of `.`, `in`, expression in `let` binding; found end of input");
+        assert_eq!(error(r"\x y x").span().0, (0, 6));
+    }
+
+    // Zero-based line and column of each error reported in recovery mode
+    fn recovered(src: &str) -> Vec<Position> {
+        let tokens = Lexer::new().generate(src).unwrap();
+        Parser::new(false, false).parse_program_recover(tokens).1.iter().map(|e| e.span().0).collect()
+    }
+
+    #[test]
+    fn stray_closing_parens_are_one_error() {
+        assert_eq!(recovered("(1 + ) )) + 2 +"), vec![(0, 5), (0, 7), (0, 15)]);
+    }
+
+    #[test]
+    fn recovery_continues_at_statements() {
+        assert_eq!(recovered("1 ) let x = 1 in x +"), vec![(0, 2), (0, 20)]);
+    }
+
+    #[test]
+    fn failed_statements_become_error_nodes() {
+        let tokens = Lexer::new().generate("let x = 1 + in\nlet y = 2 in\n(x + ) + y").unwrap();
+        let (prog, errors) = Parser::new(false, false).parse_program_recover(tokens);
+        assert_eq!(errors.len(), 2);
+        // Statement after the error is kept, the failed one binds nothing
+        assert!(matches!(&prog.0[0], (Pattern::Var(None), Expression::SpanExpr(_, e)) if matches!(**e, Expression::ErrorExpr)));
+        assert_eq!(prog.0[1].0, Pattern::Var(Some("y".to_string())));
+    }
+}
@@ -1,12 +1,16 @@
-use crate::evaluator::Environment;
+use std::fmt::{self, Write};
+use crate::evaluator::{Environment, Step};
 use crate::types::token;
 use crate::types::ast;
+use crate::types::debruijn::Term;
+use crate::substitution::unspan;
+use crate::parser::declared;
 
-pub fn print_token_stream(stream: &Vec<token::Token>) -> () {
-    print!("[");
+pub fn write_token_stream(out: &mut impl Write, stream: &[token::Token]) -> fmt::Result {
+    write!(out, "[")?;
     for (i, t) in stream.iter().enumerate() {
         // Print token
-        print!("{}", match t.0 {
+        write!(out, "{}", match t.0 {
             token::Variant::Lambda => "LAMBDA".to_string(),
             token::Variant::LParen => "LPAREN".to_string(),
             token::Variant::RParen => "RPAREN".to_string(),
@@ -27,6 +31,29 @@ pub fn print_token_stream(stream: &Vec<token::Token>) -> () {
             token::Variant::Xor => "XOR".to_string(),
             token::Variant::Let => "LET".to_string(),
             token::Variant::In => "IN".to_string(),
+            token::Variant::If => "IF".to_string(),
+            token::Variant::Then => "THEN".to_string(),
+            token::Variant::Else => "ELSE".to_string(),
+            token::Variant::Rec => "REC".to_string(),
+            token::Variant::RecAnd => "RECAND".to_string(),
+            token::Variant::Fix => "FIX".to_string(),
+            token::Variant::Colon => "COLON".to_string(),
+            token::Variant::Arrow => "ARROW".to_string(),
+            token::Variant::TypeLambda => "TYPELAMBDA".to_string(),
+            token::Variant::LBracket => "LBRACKET".to_string(),
+            token::Variant::RBracket => "RBRACKET".to_string(),
+            token::Variant::Comma => "COMMA".to_string(),
+            token::Variant::Fst => "FST".to_string(),
+            token::Variant::Snd => "SND".to_string(),
+            token::Variant::DoubleColon => "DOUBLECOLON".to_string(),
+            token::Variant::Cons => "CONS".to_string(),
+            token::Variant::Nil => "NIL".to_string(),
+            token::Variant::Head => "HEAD".to_string(),
+            token::Variant::Tail => "TAIL".to_string(),
+            token::Variant::IsNil => "ISNIL".to_string(),
+            token::Variant::Type => "TYPE".to_string(),
+            token::Variant::Match => "MATCH".to_string(),
+            token::Variant::With => "WITH".to_string(),
             token::Variant::Unit => "UNIT".to_string(),
             token::Variant::Ident => {
                 let s = match &t.1 {
@@ -49,13 +76,13 @@ pub fn print_token_stream(stream: &Vec<token::Token>) -> () {
                 };
                 "BOOLEAN(".to_string() + s + ")"
             },
-        });
+        })?;
         // Print semicolon and space
         if i < stream.len() - 1 {
-            print!("; ")
+            write!(out, "; ")?
         }
     }
-    println!("]")
+    writeln!(out, "]")
 }
 
 fn bop_to_str(x: &ast::Bop) -> String {
@@ -71,7 +98,8 @@ fn bop_to_str(x: &ast::Bop) -> String {
         ast::Bop::LteBop => "<=",
         ast::Bop::GtBop => ">",
         ast::Bop::GteBop => ">=",
-        ast::Bop::EqBop => "="
+        ast::Bop::EqBop => "=",
+        ast::Bop::ConsBop => "::"
     }).to_string()
 }
 
@@ -79,103 +107,277 @@ fn uop_to_str(x: &ast::Uop) -> String {
     (match x {
         ast::Uop::NegUop => "-",
         ast::Uop::NotUop => "!",
+        ast::Uop::HeadUop => "head",
+        ast::Uop::TailUop => "tail",
+        ast::Uop::IsNilUop => "isnil",
     }).to_string()
 }
 
-fn print_level(level: usize) {
-    for _ in 0..level { print!("| ") }
+fn write_level(out: &mut impl Write, level: usize) -> fmt::Result {
+    for _ in 0..level { write!(out, "| ")? }
+    Ok(())
 }
 
-fn print_operator(tree: &str, level: usize) {
+fn write_operator(out: &mut impl Write, tree: &str, level: usize) -> fmt::Result {
     // Print level
-    print_level(level);
+    write_level(out, level)?;
     // Print wrapper
-    print!("Operator(");
+    write!(out, "Operator(")?;
     // Print line
-    print!("{}", tree);
+    write!(out, "{}", tree)?;
     // Print closing paren
-    print!(")")
+    write!(out, ")")
 }
 
-fn print_var(tree: &str, level: usize) {
+fn write_var(out: &mut impl Write, tree: &str, level: usize) -> fmt::Result {
     // Print level
-    print_level(level);
+    write_level(out, level)?;
     // Print wrapper
-    print!("Var(");
+    write!(out, "Var(")?;
     // Print line
-    print!("{}", tree);
+    write!(out, "{}", tree)?;
     // Print closing paren
-    print!(")")
+    write!(out, ")")
 }
 
-fn print_parameters(tree: &Vec<ast::Ident>, level: usize) {
+// Pattern as written
+fn write_pattern(out: &mut impl Write, tree: &ast::Pattern) -> fmt::Result {
+    match tree {
+        ast::Pattern::Var(id) => write!(out, "{}", id.as_deref().unwrap_or("_")),
+        ast::Pattern::Tuple(plist) => {
+            write!(out, "(")?;
+            for (i, p) in plist.iter().enumerate() {
+                if i > 0 { write!(out, ", ")? }
+                write_pattern(out, p)?;
+            }
+            write!(out, ")")
+        }
+    }
+}
+
+fn write_binder(out: &mut impl Write, tree: &ast::Pattern, level: usize) -> fmt::Result {
+    match tree {
+        ast::Pattern::Var(id) => write_var(out, id.as_deref().unwrap_or("_"), level),
+        // Print level and pattern
+        ast::Pattern::Tuple(_) => {
+            write_level(out, level)?;
+            write!(out, "Pattern(")?;
+            write_pattern(out, tree)?;
+            write!(out, ")")
+        }
+    }
+}
+
+fn write_parameters(out: &mut impl Write, tree: &[ast::Pattern], types: &[Option<ast::Type>], level: usize) -> fmt::Result {
     // Print level
-    print_level(level);
+    write_level(out, level)?;
     // Print wrapper
-    print!("Parameters(");
+    write!(out, "Parameters(")?;
     // Print list of parameters
     for (i, id) in tree.iter().enumerate() {
-        match id {
-            Some(s) => print!("{}", s),
-            None => print!("_")
-        }
-        if i < tree.len() - 1 { print!(", ") }
+        write_pattern(out, id)?;
+        // Print annotation
+        if let Some(Some(t)) = types.get(i) { write!(out, ": {}", type_to_string(t))? }
+        if i < tree.len() - 1 { write!(out, ", ")? }
     }
     // Print end paren
-    print!(")")
+    write!(out, ")")
 }
 
-fn print_expression(tree: &ast::Expression, level: usize) {
+fn write_expression(out: &mut impl Write, tree: &ast::Expression, level: usize) -> fmt::Result {
+    // Spans are transparent
+    if let ast::Expression::SpanExpr(_, e) = tree { return write_expression(out, e.as_ref(), level) }
+    // Thunks print as the shared expression
+    if let ast::Expression::ThunkExpr(cell) = tree { return write_expression(out, &cell.borrow(), level) }
     // Print level
-    print_level(level);
+    write_level(out, level)?;
     // Check expression type
     match tree {
         ast::Expression::ApplicationExpr(elist) => {
             // Header
-            println!("Application Chain");
+            writeln!(out, "Application Chain")?;
             // Print list of applications
-            for (i, ex) in elist.iter().enumerate() { 
-                print_expression(ex, level + 1);
+            for (i, ex) in elist.iter().enumerate() {
+                write_expression(out, ex, level + 1)?;
                 // Newline
-                if i < elist.len() - 1 { println!() };
+                if i < elist.len() - 1 { writeln!(out)? };
             }
         },
         ast::Expression::BopExpr(b, e1, e2) => {
             // Header
-            println!("Binary Operation");
+            writeln!(out, "Binary Operation")?;
             // Operator
-            print_operator(bop_to_str(b).as_str(), level + 1);
+            write_operator(out, bop_to_str(b).as_str(), level + 1)?;
             // Newline
-            println!();
+            writeln!(out)?;
             // Expressions
-            print_expression(e1.as_ref(), level + 1);
+            write_expression(out, e1.as_ref(), level + 1)?;
             // Newline
-            println!();
-            print_expression(e2.as_ref(), level + 1);
+            writeln!(out)?;
+            write_expression(out, e2.as_ref(), level + 1)?;
         },
         ast::Expression::UopExpr(u, e) => {
             // Header
-            println!("Unary Operation");
+            writeln!(out, "Unary Operation")?;
             // Operator
-            print_operator(uop_to_str(u).as_str(), level + 1);
+            write_operator(out, uop_to_str(u).as_str(), level + 1)?;
             // Newline
-            println!();
+            writeln!(out)?;
             // Expression
-            print_expression(e.as_ref(), level + 1);
+            write_expression(out, e.as_ref(), level + 1)?;
         },
-        ast::Expression::FuncExpr(ilist, body) => {
+        ast::Expression::FuncExpr(ilist, types, body) => {
             // Header
-            println!("Function Definition");
+            writeln!(out, "Function Definition")?;
             // Parameters
-            print_parameters(ilist, level + 1);
+            write_parameters(out, ilist, types, level + 1)?;
+            // Newline
+            writeln!(out)?;
+            // Body
+            write_expression(out, body.as_ref(), level + 1)?
+        },
+        ast::Expression::IfExpr(c, t, e) => {
+            // Header
+            writeln!(out, "Conditional")?;
+            // Condition
+            write_expression(out, c.as_ref(), level + 1)?;
+            // Newline
+            writeln!(out)?;
+            // Branches
+            write_expression(out, t.as_ref(), level + 1)?;
+            // Newline
+            writeln!(out)?;
+            write_expression(out, e.as_ref(), level + 1)?
+        },
+        ast::Expression::LetExpr(id, e, body) => {
+            // Header
+            writeln!(out, "Let")?;
+            // Identifier
+            write_binder(out, id, level + 1)?;
+            // Newline
+            writeln!(out)?;
+            // Bound expression
+            write_expression(out, e.as_ref(), level + 1)?;
+            // Newline
+            writeln!(out)?;
+            // Body
+            write_expression(out, body.as_ref(), level + 1)?
+        },
+        ast::Expression::FixExpr(flist, i) => {
+            // Header
+            writeln!(out, "Fixpoint")?;
+            // Component of fixpoint, only relevant for mutual recursion
+            if flist.len() > 1 {
+                write_level(out, level + 1)?;
+                writeln!(out, "Index({})", i)?;
+            }
+            // Print list of functions
+            for (j, ex) in flist.iter().enumerate() {
+                write_expression(out, ex, level + 1)?;
+                // Newline
+                if j < flist.len() - 1 { writeln!(out)? };
+            }
+        },
+        ast::Expression::TupleExpr(elist) => {
+            // Header
+            writeln!(out, "Tuple")?;
+            // Print components
+            for (i, ex) in elist.iter().enumerate() {
+                write_expression(out, ex, level + 1)?;
+                // Newline
+                if i < elist.len() - 1 { writeln!(out)? };
+            }
+        },
+        ast::Expression::ListExpr(elist) => {
+            // Header
+            writeln!(out, "List")?;
+            // Print elements
+            for (i, ex) in elist.iter().enumerate() {
+                write_expression(out, ex, level + 1)?;
+                // Newline
+                if i < elist.len() - 1 { writeln!(out)? };
+            }
+        },
+        ast::Expression::ConstructExpr(d, i, elist) => {
+            // Header
+            writeln!(out, "Constructor")?;
+            // Constructor name
+            write_level(out, level + 1)?;
+            write!(out, "Name({})", d.constructors[*i].0)?;
+            // Print fields
+            for ex in elist.iter() {
+                writeln!(out)?;
+                write_expression(out, ex, level + 1)?;
+            }
+        },
+        ast::Expression::MatchExpr(e, cases) => {
+            // Header
+            writeln!(out, "Match")?;
+            // Scrutinee
+            write_expression(out, e.as_ref(), level + 1)?;
+            // Print cases
+            for case in cases.iter() {
+                writeln!(out)?;
+                write_level(out, level + 1)?;
+                writeln!(out, "Case")?;
+                // Constructor, none for a variable case
+                if let Some((d, i)) = &case.constructor {
+                    write_level(out, level + 2)?;
+                    writeln!(out, "Name({})", d.constructors[*i].0)?;
+                }
+                // Variables bound
+                for var in case.vars.iter() {
+                    write_var(out, var.as_deref().unwrap_or("_"), level + 2)?;
+                    writeln!(out)?;
+                }
+                // Body
+                write_expression(out, &case.body, level + 2)?;
+            }
+        },
+        ast::Expression::ProjExpr(e, i, _) => {
+            // Header
+            writeln!(out, "Projection")?;
+            // Component
+            write_level(out, level + 1)?;
+            writeln!(out, "Index({})", i)?;
+            // Tuple
+            write_expression(out, e.as_ref(), level + 1)?
+        },
+        ast::Expression::TypeFuncExpr(a, body) => {
+            // Header
+            writeln!(out, "Type Abstraction")?;
+            // Type variable
+            write_var(out, a, level + 1)?;
             // Newline
-            println!();
+            writeln!(out)?;
+            // Body
+            write_expression(out, body.as_ref(), level + 1)?
+        },
+        ast::Expression::DeclExpr(d, body) => {
+            // Header
+            writeln!(out, "Type Declaration")?;
+            // Declaration
+            write_level(out, level + 1)?;
+            write!(out, "Declaration(")?;
+            write_declaration(out, d)?;
+            writeln!(out, ")")?;
             // Body
-            print_expression(body.as_ref(), level + 1)
+            write_expression(out, body.as_ref(), level + 1)?
+        },
+        ast::Expression::TypeAppExpr(e, t) => {
+            // Header
+            writeln!(out, "Type Application")?;
+            // Expression
+            write_expression(out, e.as_ref(), level + 1)?;
+            // Newline
+            writeln!(out)?;
+            // Type argument
+            write_level(out, level + 1)?;
+            write!(out, "Type({})", type_to_string(t))?
         },
         ast::Expression::ValExpr(v) => {
             // Print value
-            print!("{}", match v {
+            write!(out, "{}", match v {
                 ast::Value::Boolean(x) => {
                     let s = if *x {"true"} else {"false"};
                     "Bool(".to_string() + s + ")"
@@ -192,162 +394,752 @@ fn print_expression(tree: &ast::Expression, level: usize) {
                 },
                 ast::Value::Closure(_,_,_) => {
                     "Closure".to_string()
+                },
+                ast::Value::Tuple(elist) => {
+                    "Tuple(".to_string() + &elist.iter().map(|e| grouped_expression_to_string(e, false)).collect::<Vec<_>>().join(", ") + ")"
+                },
+                ast::Value::List(elist) => {
+                    "List(".to_string() + &elist.iter().map(|e| grouped_expression_to_string(e, false)).collect::<Vec<_>>().join(", ") + ")"
+                },
+                ast::Value::Data(d, i, elist) => {
+                    "Data(".to_string() + &d.constructors[*i].0 + &elist.iter().map(|e| " ".to_string() + &grouped_expression_to_string(e, true)).collect::<String>() + ")"
                 }
-            })
+            })?
         },
         ast::Expression::EnvExpr(_, e) => {
-            print_expression(e.as_ref(), level)
-        }
-    }
+            write_expression(out, e.as_ref(), level)?
+        },
+        ast::Expression::ThunkExpr(_) => (),
+        ast::Expression::ErrorExpr => {
+            write!(out, "Error")?
+        },
+        ast::Expression::SpanExpr(_, _) => ()
+    };
+    Ok(())
 }
 
-fn print_statement(tree: &ast::Statement, level: usize) {
+fn write_statement(out: &mut impl Write, tree: &ast::Statement, level: usize) -> fmt::Result {
     // Print level
-    print_level(level);
+    write_level(out, level)?;
+    // Declaration scoping over the rest of the program
+    if let Some(d) = declared(tree) {
+        writeln!(out, "Type Declaration")?;
+        write_level(out, level + 1)?;
+        write!(out, "Declaration(")?;
+        write_declaration(out, d)?;
+        return write!(out, ")")
+    }
     // Header
-    println!("Let");
+    writeln!(out, "Let")?;
     // Identifier
-    match &tree.0 {
-        Some(s) => print_var(s, level + 1),
-        None => print_var("_", level + 1)
-    };
+    write_binder(out, &tree.0, level + 1)?;
     // Newline
-    println!();
+    writeln!(out)?;
     // Expression
-    print_expression(&tree.1, level + 1);
+    write_expression(out, &tree.1, level + 1)
 }
 
-pub fn print_program(tree: &ast::Program) {
+pub fn write_program(out: &mut impl Write, tree: &ast::Program) -> fmt::Result {
     // Header
-    println!("Program");
+    writeln!(out, "Program")?;
     // Print statements
-    for s in &tree.0 {
-        print_statement(s, 1);
+    for s in tree.0.iter() {
+        write_statement(out, s, 1)?;
         // Newline
-        println!();
+        writeln!(out)?;
     };
     // Print expression
-    print_expression(&tree.1, 1);
+    write_expression(out, &tree.1, 1)?;
     // Newline
-    println!()
+    writeln!(out)
+}
+
+// Names, annotated types, and bodies of a recursive group
+type RecGroup<'a> = (&'a [ast::Pattern], &'a [Option<ast::Type>], Vec<&'a ast::Expression>);
+
+// Group of recursive functions, when every function takes the group by the same names and types
+fn rec_group(flist: &[ast::Expression]) -> Option<RecGroup<'_>> {
+    let (names, types) = match unspan(flist.first()?) {
+        ast::Expression::FuncExpr(params, types, _) => (params, types),
+        _ => return None
+    };
+    if names.len() != flist.len() || !names.iter().all(|p| matches!(p, ast::Pattern::Var(Some(_)))) { return None }
+    let bodies = flist.iter().map(|f| match unspan(f) {
+        ast::Expression::FuncExpr(params, ptypes, body) if params == names && ptypes == types => Some(body.as_ref()),
+        _ => None
+    }).collect::<Option<Vec<_>>>()?;
+    Some((names, types, bodies))
+}
+
+// Group bound by leading bindings of each of its names to its own component, as a let rec statement is parsed
+fn rec_bindings<'a>(bindings: &[(&'a ast::Pattern, &'a ast::Expression)]) -> Option<RecGroup<'a>> {
+    let group = match unspan(bindings.first()?.1) {
+        ast::Expression::FixExpr(flist, 0) => rec_group(flist)?,
+        _ => return None
+    };
+    let names = group.0;
+    if bindings.len() < names.len() { return None }
+    for (j, (id, e)) in bindings.iter().take(names.len()).enumerate() {
+        if **id != names[j] || !matches!(unspan(e), ast::Expression::FixExpr(flist, i) if *i == j && flist.len() == names.len()) { return None }
+    }
+    Some(group)
+}
+
+fn write_rec(out: &mut impl Write, group: &RecGroup) -> fmt::Result {
+    let (names, types, bodies) = group;
+    // Bindings separated by and keywords
+    write!(out, "let rec ")?;
+    for (j, (id, body)) in names.iter().zip(bodies.iter()).enumerate() {
+        if j > 0 { write!(out, " and ")? }
+        write_pattern(out, id)?;
+        // Print annotation
+        if let Some(Some(t)) = types.get(j) { write!(out, ": {}", type_to_string(t))? }
+        write!(out, " = ")?;
+        write_grouped_expression(out, body, false)?;
+    }
+    Ok(())
 }
 
-pub fn print_grouped_expression(tree: &ast::Expression, outer: bool) {
-    // Always disable outer parenthesis for values and enable for functions
+pub fn write_grouped_expression(out: &mut impl Write, tree: &ast::Expression, outer: bool) -> fmt::Result {
+    // Spans are transparent
+    if let ast::Expression::SpanExpr(_, e) = tree { return write_grouped_expression(out, e.as_ref(), outer) }
+    // Environments and thunks print as the expression inside
+    if let ast::Expression::EnvExpr(_, e) = tree { return write_grouped_expression(out, e.as_ref(), outer) }
+    if let ast::Expression::ThunkExpr(cell) = tree { return write_grouped_expression(out, &cell.borrow(), outer) }
+    // Always disable outer parenthesis for values and enable for functions and conditionals
     let mut outer = outer;
-    match tree { 
-        ast::Expression::ValExpr(_) => outer = false, 
-        ast::Expression::FuncExpr(_, _) => outer = true, 
-        _ => () 
+    match tree {
+        // Constructors applied to fields are grouped like applications
+        ast::Expression::ValExpr(ast::Value::Data(_, _, elist)) | ast::Expression::ConstructExpr(_, _, elist) if !elist.is_empty() => (),
+        ast::Expression::ConstructExpr(_, _, _) => outer = false,
+        ast::Expression::MatchExpr(_, _) => outer = true,
+        ast::Expression::ValExpr(_) => outer = false,
+        ast::Expression::TupleExpr(_) => outer = false,
+        ast::Expression::ListExpr(_) => outer = false,
+        ast::Expression::FuncExpr(_, _, _) => outer = true,
+        ast::Expression::TypeFuncExpr(_, _) => outer = true,
+        ast::Expression::DeclExpr(_, _) => outer = true,
+        ast::Expression::IfExpr(_, _, _) => outer = true,
+        ast::Expression::LetExpr(_, _, _) => outer = true,
+        // Component of a mutually recursive group prints as a let rec expression
+        ast::Expression::FixExpr(flist, _) if flist.len() > 1 && rec_group(flist).is_some() => outer = true,
+        _ => ()
     }
     // Opening paren
-    if outer { print!("(") }
+    if outer { write!(out, "(")? }
     // Expression
     match tree {
         ast::Expression::ApplicationExpr(alist) => {
             // Print first item in expression
-            print_grouped_expression(alist.first().unwrap(), true);
+            write_grouped_expression(out, alist.first().unwrap(), true)?;
             // Print space
-            print!(" ");
+            write!(out, " ")?;
             // Print rest of items in application
             for (i, ex) in alist[1..].iter().enumerate() {
                 // Print expression
-                print_grouped_expression(ex, true);
+                write_grouped_expression(out, ex, true)?;
                 // Print space except for last item
-                if i < alist.len() - 2 { print!(" ") }
+                if i < alist.len() - 2 { write!(out, " ")? }
             }
         },
         ast::Expression::BopExpr(b, e1, e2) => {
             // Print first expression
-            print_grouped_expression(e1.as_ref(), true);
+            write_grouped_expression(out, e1.as_ref(), true)?;
             // Print bop
-            print!(" {} ", bop_to_str(b));
+            write!(out, " {} ", bop_to_str(b))?;
             // Print second expression
-            print_grouped_expression(e2.as_ref(), true);
+            write_grouped_expression(out, e2.as_ref(), true)?;
         },
-        ast::Expression::FuncExpr(plist, body) => {
+        ast::Expression::FuncExpr(plist, types, body) => {
             // Print lambda
-            print!("\\");
+            write!(out, "\\")?;
             // Print parameters
             for (i, p) in plist.iter().enumerate() {
-                match p {
-                    Some(s) => print!("{}", s),
-                    None => print!("_")
-                }
-                if i < plist.len() - 1 { print!(" ") }
+                write_pattern(out, p)?;
+                // Print annotation
+                if let Some(Some(t)) = types.get(i) { write!(out, ": {}", type_to_string(t))? }
+                if i < plist.len() - 1 { write!(out, " ")? }
             };
             // Dot
-            print!(". ");
+            write!(out, ". ")?;
             // Body
-            print_grouped_expression(body.as_ref(), false);
+            write_grouped_expression(out, body.as_ref(), false)?;
+        },
+        ast::Expression::IfExpr(c, t, e) => {
+            // Condition
+            write!(out, "if ")?;
+            write_grouped_expression(out, c.as_ref(), false)?;
+            // Branches
+            write!(out, " then ")?;
+            write_grouped_expression(out, t.as_ref(), false)?;
+            write!(out, " else ")?;
+            write_grouped_expression(out, e.as_ref(), false)?;
+        },
+        ast::Expression::LetExpr(id, e, body) => {
+            // Bindings of a mutually recursive group, followed by the body after them
+            let mut bindings = vec![(id, e.as_ref())];
+            let mut rest = body.as_ref();
+            while let ast::Expression::LetExpr(id1, e1, body1) = unspan(rest) {
+                bindings.push((id1, e1.as_ref()));
+                rest = body1.as_ref();
+            }
+            if let Some(group) = rec_bindings(&bindings) {
+                write_rec(out, &group)?;
+                write!(out, " in ")?;
+                // Body follows as many bindings as the group has names
+                let mut rest = tree;
+                for _ in group.0.iter() {
+                    if let ast::Expression::LetExpr(_, _, body1) = unspan(rest) { rest = body1.as_ref() }
+                }
+                write_grouped_expression(out, rest, false)?;
+                if outer { write!(out, ")")? }
+                return Ok(())
+            }
+            // Binding
+            write!(out, "let ")?;
+            write_pattern(out, id)?;
+            write!(out, " = ")?;
+            write_grouped_expression(out, e.as_ref(), false)?;
+            // Body
+            write!(out, " in ")?;
+            write_grouped_expression(out, body.as_ref(), false)?;
+        },
+        ast::Expression::FixExpr(flist, i) if flist.len() > 1 && rec_group(flist).is_some() => {
+            // Group bound by a let rec, naming the component
+            let group = rec_group(flist).unwrap();
+            write_rec(out, &group)?;
+            write!(out, " in ")?;
+            write_pattern(out, &group.0[*i])?;
+        },
+        ast::Expression::FixExpr(flist, i) => {
+            // Mutual recursion whose functions disagree on names prints as a component of the fixpoint of all functions
+            if flist.len() > 1 { write!(out, "(")? }
+            // Fix keyword
+            write!(out, "fix")?;
+            // Functions
+            for ex in flist.iter() {
+                write!(out, " ")?;
+                write_grouped_expression(out, ex, true)?;
+            }
+            // Index
+            if flist.len() > 1 { write!(out, ").{}", i)? }
+        },
+        ast::Expression::TypeFuncExpr(a, body) => {
+            // Type variable
+            write!(out, "/\\{}. ", a)?;
+            // Body
+            write_grouped_expression(out, body.as_ref(), false)?;
+        },
+        ast::Expression::DeclExpr(d, body) => {
+            // Declaration
+            write!(out, "type ")?;
+            write_declaration(out, d)?;
+            // Body
+            write!(out, " in ")?;
+            write_grouped_expression(out, body.as_ref(), false)?;
+        },
+        ast::Expression::TupleExpr(elist) => write_sequence(out, elist, "(", ")")?,
+        ast::Expression::ListExpr(elist) => write_sequence(out, elist, "[", "]")?,
+        ast::Expression::ConstructExpr(d, i, elist) => write_construct(out, d, *i, elist)?,
+        ast::Expression::MatchExpr(e, cases) => {
+            // Scrutinee
+            write!(out, "match ")?;
+            write_grouped_expression(out, e.as_ref(), false)?;
+            write!(out, " with")?;
+            for case in cases.iter() {
+                // Pattern
+                write!(out, " | ")?;
+                if let Some((d, i)) = &case.constructor { write!(out, "{}", d.constructors[*i].0)? }
+                for (j, var) in case.vars.iter().enumerate() {
+                    if case.constructor.is_some() || j > 0 { write!(out, " ")? }
+                    write!(out, "{}", var.as_deref().unwrap_or("_"))?
+                }
+                // Body, a disjunction would be read as the next case
+                write!(out, " -> ")?;
+                let or = matches!(unspan(&case.body), ast::Expression::BopExpr(ast::Bop::OrBop, _, _));
+                write_grouped_expression(out, &case.body, or)?;
+            }
+        },
+        ast::Expression::ProjExpr(e, i, _) => {
+            // Tuple, then component index
+            write_grouped_expression(out, e.as_ref(), true)?;
+            write!(out, ".{}", i)?;
+        },
+        ast::Expression::TypeAppExpr(e, t) => {
+            // Expression, then type argument in brackets
+            write_grouped_expression(out, e.as_ref(), true)?;
+            write!(out, " [{}]", type_to_string(t))?;
         },
         ast::Expression::UopExpr(op, body) => {
-            // Operator
-            print!("{}", uop_to_str(op));
+            // Operator, list operations are words separated from their operand
+            match op {
+                ast::Uop::NegUop | ast::Uop::NotUop => write!(out, "{}", uop_to_str(op))?,
+                _ => write!(out, "{} ", uop_to_str(op))?
+            }
             // Expression
-            print_grouped_expression(body.as_ref(), true);
+            write_grouped_expression(out, body.as_ref(), true)?;
         },
         ast::Expression::ValExpr(v) => {
-            print!("{}", match v {
-                ast::Value::Boolean(x) => (if *x { "true" } else { "false" }).to_string(),
-                ast::Value::Identifier(x) => x.to_string(),
-                ast::Value::Number(x) => x.to_string(),
-                ast::Value::Unit => "_".to_string(),
+            match v {
+                ast::Value::Boolean(x) => write!(out, "{}", if *x { "true" } else { "false" })?,
+                ast::Value::Identifier(x) => write!(out, "{}", x)?,
+                ast::Value::Number(x) => write!(out, "{}", x)?,
+                ast::Value::Unit => write!(out, "_")?,
+                ast::Value::Tuple(elist) => write_sequence(out, elist, "(", ")")?,
+                ast::Value::List(elist) => write_sequence(out, elist, "[", "]")?,
+                ast::Value::Data(d, i, elist) => write_construct(out, d, *i, elist)?,
                 ast::Value::Closure(params, body, env) => {
                     // Print closure keyword
-                    print!("closure(");
+                    write!(out, "closure(")?;
                     // Print function
-                    print_grouped_expression(&ast::Expression::FuncExpr(params.clone(), body.clone()), false);
+                    write_grouped_expression(out, &ast::Expression::FuncExpr(params.clone(), vec![None; params.len()], body.clone()), false)?;
                     // Print separator
-                    print!(", ");
+                    write!(out, ", ")?;
                     // Print environment
-                    print_environment(env);
+                    write_environment(out, env)?;
                     // Print closing paren
-                    print!(")");
-                    // Return nothing
-                    "".to_string()
+                    write!(out, ")")?
                 }
-            })
+            }
         },
-        ast::Expression::EnvExpr(_, e) => {
-            print_grouped_expression(e.as_ref(), false)
-        }
+        ast::Expression::EnvExpr(_, _) => (),
+        ast::Expression::ThunkExpr(_) => (),
+        ast::Expression::ErrorExpr => {
+            write!(out, "<error>")?
+        },
+        ast::Expression::SpanExpr(_, _) => ()
     }
     // Closing paren
-    if outer { print!(")") }
+    if outer { write!(out, ")")? }
+    Ok(())
 }
 
-pub fn print_group(tree: &ast::Program) {
+pub fn write_group(out: &mut impl Write, tree: &ast::Program) -> fmt::Result {
     // Print statements
-    for s in &tree.0 {
-        // Let keyword
-        print!("let ");
-        // Identifier
-        match &s.0 {
-            Some(s) => print!("{}", s),
-            None => print!("_")
+    let stmts = &tree.0;
+    let mut k = 0;
+    while k < stmts.len() {
+        let s = &stmts[k];
+        k += 1;
+        // Declaration scoping over the rest of the program
+        if let Some(d) = declared(s) {
+            write!(out, "type ")?;
+            write_declaration(out, d)?;
+            writeln!(out, " in")?;
+            continue
+        }
+        // Mutually recursive group, spanning one statement per name
+        let bindings: Vec<(&ast::Pattern, &ast::Expression)> = stmts[k - 1..].iter().map(|s| (&s.0, &s.1)).collect();
+        if let Some(group) = rec_bindings(&bindings) {
+            write_rec(out, &group)?;
+            writeln!(out, " in")?;
+            k += group.0.len() - 1;
+            continue
         }
+        // Let keyword and identifier
+        write!(out, "let ")?;
+        write_pattern(out, &s.0)?;
         // = keyword
-        print!(" = ");
+        write!(out, " = ")?;
         // Expression
-        print_grouped_expression(&s.1, false);
+        write_grouped_expression(out, &s.1, false)?;
         // in keyword and newline
-        println!(" in")
+        writeln!(out, " in")?
     };
     // Print expression
-    print_grouped_expression(&tree.1, false);
+    write_grouped_expression(out, &tree.1, false)?;
     // Newline
-    println!()
+    writeln!(out)
+}
+
+pub fn write_trace_step(out: &mut impl Write, step: &Step) -> fmt::Result {
+    // Step number
+    write!(out, "{:>4}  ", step.number)?;
+    // Rule that fired, blank for the initial expression
+    match step.rule {
+        Some(r) => write!(out, "{:<10}", "[".to_string() + r.name() + "]")?,
+        None => write!(out, "{:<10}", "")?
+    }
+    // Expression after step
+    write_grouped_expression(out, step.expr, false)?;
+    // Newline
+    writeln!(out)?;
+    // Substitution performed by step, lined up with expression
+    if let Some(sub) = &step.substitution {
+        for (old, new) in sub.renames.iter() {
+            writeln!(out, "{:16}renamed {} to {}", "", old, new)?;
+        }
+        write!(out, "{:16}[", "")?;
+        write_pattern(out, &sub.var)?;
+        write!(out, " := ")?;
+        write_grouped_expression(out, &sub.value, false)?;
+        write!(out, "]")?;
+        write_grouped_expression(out, &sub.body, true)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn write_sequence(out: &mut impl Write, elist: &[ast::Expression], open: &str, close: &str) -> fmt::Result {
+    // Components separated by commas, tuples and lists bring their own delimiters
+    write!(out, "{}", open)?;
+    for (i, ex) in elist.iter().enumerate() {
+        if i > 0 { write!(out, ", ")? }
+        write_grouped_expression(out, ex, false)?;
+    }
+    write!(out, "{}", close)
+}
+
+// Type with references to the declared type by name alone, as written in its declaration
+fn unapplied(t: &ast::Type, name: &str) -> ast::Type {
+    match t {
+        ast::Type::Data(n, _) if n == name => ast::Type::Data(n.clone(), Vec::new().into()),
+        ast::Type::Data(n, tlist) => ast::Type::Data(n.clone(), tlist.iter().map(|t1| unapplied(t1, name)).collect()),
+        ast::Type::Arrow(t1, t2) => ast::Type::Arrow(Box::new(unapplied(t1, name)), Box::new(unapplied(t2, name))),
+        ast::Type::Tuple(tlist) => ast::Type::Tuple(tlist.iter().map(|t1| unapplied(t1, name)).collect()),
+        ast::Type::List(t1) => ast::Type::List(Box::new(unapplied(t1, name))),
+        ast::Type::Forall(a, t1) => ast::Type::Forall(a.clone(), Box::new(unapplied(t1, name))),
+        _ => t.clone()
+    }
+}
+
+fn write_declaration(out: &mut impl Write, d: &ast::DataType) -> fmt::Result {
+    // Name, then constructors separated by bars
+    write!(out, "{} =", d.name)?;
+    for (i, (c, fields)) in d.constructors.iter().enumerate() {
+        if i > 0 { write!(out, " |")? }
+        write!(out, " {}", c)?;
+        // Fields are type atoms, anything but a single name is parenthesised
+        for t in fields.iter().map(|t| unapplied(t, &d.name)) {
+            match &t {
+                ast::Type::Data(_, tlist) if !tlist.is_empty() => write!(out, " ({})", type_to_string(&t))?,
+                ast::Type::Data(_, _) | ast::Type::Int | ast::Type::Bool | ast::Type::Unit | ast::Type::Named(_) => write!(out, " {}", type_to_string(&t))?,
+                _ => write!(out, " ({})", type_to_string(&t))?
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_construct(out: &mut impl Write, d: &ast::DataType, i: usize, elist: &[ast::Expression]) -> fmt::Result {
+    // Constructor name followed by its fields
+    write!(out, "{}", d.constructors[i].0)?;
+    for ex in elist.iter() {
+        write!(out, " ")?;
+        write_grouped_expression(out, ex, true)?;
+    }
+    Ok(())
+}
+
+pub fn write_debruijn(out: &mut impl Write, tree: &Term, base: usize, outer: bool) -> fmt::Result {
+    // Always disable outer parenthesis for atoms and enable for functions and conditionals
+    let outer = match tree {
+        Term::Var(_) | Term::Free(_) | Term::Num(_) | Term::Bool(_) | Term::Unit | Term::Error | Term::Tuple(_) | Term::Proj(_, _) | Term::List(_) => false,
+        Term::Construct(_, _, tlist) if tlist.is_empty() => false,
+        Term::Lam(_) | Term::Let(_, _) | Term::If(_, _, _) | Term::Match(_, _) => true,
+        _ => outer
+    };
+    // Opening paren
+    if outer { write!(out, "(")? }
+    match tree {
+        Term::Var(i) => write!(out, "{}", i + base)?,
+        Term::Free(x) => write!(out, "{}", x)?,
+        Term::Num(n) => write!(out, "{}", n)?,
+        Term::Bool(b) => write!(out, "{}", if *b { "true" } else { "false" })?,
+        Term::Unit => write!(out, "_")?,
+        Term::Error => write!(out, "<error>")?,
+        Term::Lam(_) => {
+            // Print consecutive lambdas without parens in between
+            let mut body = tree;
+            while let Term::Lam(t) = body {
+                write!(out, "\\. ")?;
+                body = t.as_ref();
+            }
+            write_debruijn(out, body, base, false)?;
+        },
+        Term::App(t1, t2) => {
+            // Application associates to the left
+            let left_outer = !matches!(t1.as_ref(), Term::App(_, _));
+            write_debruijn(out, t1, base, left_outer)?;
+            write!(out, " ")?;
+            write_debruijn(out, t2, base, true)?;
+        },
+        Term::Let(t1, t2) => {
+            // Binder is nameless
+            write!(out, "let ")?;
+            write_debruijn(out, t1, base, false)?;
+            write!(out, " in ")?;
+            write_debruijn(out, t2, base, false)?;
+        },
+        Term::Fix(tlist, i) => {
+            if tlist.len() > 1 { write!(out, "(")? }
+            write!(out, "fix")?;
+            for t in tlist.iter() {
+                write!(out, " ")?;
+                write_debruijn(out, t, base, true)?;
+            }
+            if tlist.len() > 1 { write!(out, ").{}", i)? }
+        },
+        Term::Tuple(tlist) => {
+            write!(out, "(")?;
+            for (i, t) in tlist.iter().enumerate() {
+                if i > 0 { write!(out, ", ")? }
+                write_debruijn(out, t, base, false)?;
+            }
+            write!(out, ")")?;
+        },
+        Term::Proj(t, i) => {
+            write_debruijn(out, t, base, true)?;
+            write!(out, ".{}", i)?;
+        },
+        Term::List(tlist) => {
+            write!(out, "[")?;
+            for (i, t) in tlist.iter().enumerate() {
+                if i > 0 { write!(out, ", ")? }
+                write_debruijn(out, t, base, false)?;
+            }
+            write!(out, "]")?;
+        },
+        Term::Construct(d, i, tlist) => {
+            write!(out, "{}", d.constructors[*i].0)?;
+            for t in tlist.iter() {
+                write!(out, " ")?;
+                write_debruijn(out, t, base, true)?;
+            }
+        },
+        Term::Match(t, tcases) => {
+            // Case variables are nameless
+            write!(out, "match ")?;
+            write_debruijn(out, t, base, false)?;
+            write!(out, " with")?;
+            for (constructor, nvars, body) in tcases.iter() {
+                write!(out, " | ")?;
+                let mut pattern = vec!["_"; *nvars];
+                if let Some((d, i)) = constructor { pattern.insert(0, &d.constructors[*i].0) }
+                write!(out, "{} -> ", pattern.join(" "))?;
+                let or = matches!(body, Term::Bop(ast::Bop::OrBop, _, _));
+                write_debruijn(out, body, base, or)?;
+            }
+        },
+        Term::If(c, t, e) => {
+            write!(out, "if ")?;
+            write_debruijn(out, c, base, false)?;
+            write!(out, " then ")?;
+            write_debruijn(out, t, base, false)?;
+            write!(out, " else ")?;
+            write_debruijn(out, e, base, false)?;
+        },
+        Term::Uop(op, t) => {
+            match op {
+                ast::Uop::NegUop | ast::Uop::NotUop => write!(out, "{}", uop_to_str(op))?,
+                _ => write!(out, "{} ", uop_to_str(op))?
+            }
+            write_debruijn(out, t, base, true)?;
+        },
+        Term::Bop(op, t1, t2) => {
+            write_debruijn(out, t1, base, true)?;
+            write!(out, " {} ", bop_to_str(op))?;
+            write_debruijn(out, t2, base, true)?;
+        }
+    }
+    // Closing paren
+    if outer { write!(out, ")")? }
+    Ok(())
 }
 
-pub fn print_environment(env: &Environment) {
+pub fn write_environment(out: &mut impl Write, env: &Environment) -> fmt::Result {
     // Print open bracket
-    print!("[");
+    write!(out, "[")?;
     for (i, item) in env.data().iter().enumerate() {
-        print!("{} = ", &item.0);
-        print_grouped_expression(&item.1, false);
-        if i < env.data().len() - 1 { print!("; ") };
+        write!(out, "{} = ", &item.0)?;
+        write_grouped_expression(out, &item.1, false)?;
+        if i < env.data().len() - 1 { write!(out, "; ")? };
     }
     // Print closed bracket
-    print!("]")
-}
\ No newline at end of file
+    write!(out, "]")
+}
+
+// String rendering, writing into a String cannot fail
+
+pub fn token_stream_to_string(stream: &[token::Token]) -> String {
+    let mut s = String::new();
+    write_token_stream(&mut s, stream).unwrap();
+    s
+}
+
+pub fn pattern_to_string(tree: &ast::Pattern) -> String {
+    let mut s = String::new();
+    write_pattern(&mut s, tree).unwrap();
+    s
+}
+
+pub fn program_to_string(tree: &ast::Program) -> String {
+    let mut s = String::new();
+    write_program(&mut s, tree).unwrap();
+    s
+}
+
+pub fn grouped_expression_to_string(tree: &ast::Expression, outer: bool) -> String {
+    let mut s = String::new();
+    write_grouped_expression(&mut s, tree, outer).unwrap();
+    s
+}
+
+pub fn group_to_string(tree: &ast::Program) -> String {
+    let mut s = String::new();
+    write_group(&mut s, tree).unwrap();
+    s
+}
+
+pub fn environment_to_string(env: &Environment) -> String {
+    let mut s = String::new();
+    write_environment(&mut s, env).unwrap();
+    s
+}
+
+pub fn trace_step_to_string(step: &Step) -> String {
+    let mut s = String::new();
+    write_trace_step(&mut s, step).unwrap();
+    s
+}
+
+pub fn debruijn_to_string(tree: &Term, base: usize) -> String {
+    let mut s = String::new();
+    write_debruijn(&mut s, tree, base, false).unwrap();
+    s
+}
+
+// Name of the nth type variable, a to z then a1 onwards
+fn type_var_name(n: usize) -> String {
+    let letter = ((b'a' + (n % 26) as u8) as char).to_string();
+    if n < 26 { letter } else { letter + &(n / 26).to_string() }
+}
+
+fn write_type(out: &mut impl Write, t: &ast::Type, names: &mut Vec<usize>, outer: bool) -> fmt::Result {
+    match t {
+        ast::Type::Int => write!(out, "int"),
+        ast::Type::Bool => write!(out, "bool"),
+        ast::Type::Unit => write!(out, "unit"),
+        ast::Type::Var(v) => {
+            // Variables are named in order of appearance
+            let n = match names.iter().position(|x| x == v) {
+                Some(n) => n,
+                None => {
+                    names.push(*v);
+                    names.len() - 1
+                }
+            };
+            write!(out, "{}", type_var_name(n))
+        },
+        ast::Type::Named(a) => write!(out, "{}", a),
+        ast::Type::Forall(a, t1) => {
+            // Quantifier extends as far right as possible
+            if outer { write!(out, "(")? }
+            write!(out, "forall {}", a)?;
+            // Consecutive quantifiers share one forall
+            let mut body = t1.as_ref();
+            while let ast::Type::Forall(b, t2) = body {
+                write!(out, " {}", b)?;
+                body = t2.as_ref();
+            }
+            write!(out, ". ")?;
+            write_type(out, body, names, false)?;
+            if outer { write!(out, ")")? }
+            Ok(())
+        },
+        ast::Type::Tuple(tlist) => {
+            // Components that are tuples or functions need parens
+            if outer { write!(out, "(")? }
+            for (i, t1) in tlist.iter().enumerate() {
+                if i > 0 { write!(out, " * ")? }
+                write_type(out, t1, names, true)?;
+            }
+            if outer { write!(out, ")")? }
+            Ok(())
+        },
+        ast::Type::List(t1) => {
+            // Element type comes first, parenthesised unless it is an atom or a list
+            write_type(out, t1, names, true)?;
+            write!(out, " list")
+        },
+        ast::Type::Data(name, tlist) => {
+            // Arguments come first like list elements, several are grouped in parens
+            match tlist.len() {
+                0 => (),
+                1 => {
+                    write_type(out, &tlist[0], names, true)?;
+                    write!(out, " ")?
+                },
+                _ => {
+                    write!(out, "(")?;
+                    for (i, t1) in tlist.iter().enumerate() {
+                        if i > 0 { write!(out, ", ")? }
+                        write_type(out, t1, names, false)?;
+                    }
+                    write!(out, ") ")?
+                }
+            }
+            write!(out, "{}", name)
+        },
+        ast::Type::Arrow(t1, t2) => {
+            // Arrows associate to the right, so only a function on the left needs parens
+            if outer { write!(out, "(")? }
+            write_type(out, t1, names, true)?;
+            write!(out, " -> ")?;
+            write_type(out, t2, names, false)?;
+            if outer { write!(out, ")")? }
+            Ok(())
+        }
+    }
+}
+
+pub fn type_to_string(t: &ast::Type) -> String {
+    let mut s = String::new();
+    write_type(&mut s, t, &mut Vec::new(), false).unwrap();
+    s
+}
+
+// Render types together so that the same variable has the same name in each
+pub fn types_to_strings(tlist: &[ast::Type]) -> Vec<String> {
+    let mut names = Vec::new();
+    tlist.iter().map(|t| {
+        let mut s = String::new();
+        write_type(&mut s, t, &mut names, false).unwrap();
+        s
+    }).collect()
+}
+
+// Quantified variables are listed in order of appearance
+pub fn scheme_to_string(s: &ast::Scheme) -> String {
+    let mut body = String::new();
+    let mut names = Vec::new();
+    write_type(&mut body, &s.body, &mut names, false).unwrap();
+    let quantified: Vec<String> = names.iter().enumerate()
+        .filter(|(_, v)| s.vars.contains(v))
+        .map(|(n, _)| type_var_name(n))
+        .collect();
+    if quantified.is_empty() { body } else { format!("forall {}. {}", quantified.join(" "), body) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn group(src: &str) -> String {
+        let tokens = Lexer::new().generate(src).unwrap();
+        group_to_string(&Parser::new(false, false).parse_program(tokens).unwrap())
+    }
+
+    #[test]
+    fn single_let_rec_prints_as_let_rec() {
+        let printed = group(r"let rec f = \n. f n in f 1");
+        assert_eq!(printed, "let rec f = (\\n. f n) in\nf 1\n");
+        assert_eq!(group(&printed), printed);
+    }
+
+    #[test]
+    fn fix_outside_a_binding_prints_as_fix() {
+        assert_eq!(group(r"fix (\f n. n)"), "fix (\\f n. n)\n");
+    }
+}
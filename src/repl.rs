@@ -0,0 +1,245 @@
+use ltk::diagnostic;
+use ltk::error::Error;
+use ltk::evaluator::Evaluator;
+use ltk::lexer::Lexer;
+use ltk::parser::Parser;
+use ltk::printing;
+use ltk::systemf;
+use ltk::typechecker::TypeChecker;
+use ltk::Pipeline;
+use ltk::types::ast::{Expression, Statement, Value};
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+const PROMPT: &str = "ltk> ";
+const CONTINUE_PROMPT: &str = "...> ";
+
+const HELP: &str = "\
+Enter an expression, or statements of the form 'let x = e in' to extend the environment.
+Commands:
+  :env          Print the current environment
+  :reset        Clear the environment
+  :load <file>  Evaluate a program file in the current environment
+  :help         Show this message
+  :quit         Exit the REPL";
+
+// Outcome of attempting to evaluate buffered input
+enum Outcome {
+    Done(Result<Option<Expression>, Error>),
+    Incomplete
+}
+
+// Interactive session with a persistent environment
+pub struct Repl {
+    lex: Lexer,
+    parse: Parser,
+    evaluate: Evaluator,
+    dynamic: bool,
+    // Check types before evaluating, always done for System F
+    typecheck: bool,
+    systemf: bool,
+    // Statements accepted so far, checked again with each input so later input sees their types
+    statements: Vec<Statement>,
+    color: bool
+}
+impl Repl {
+    // Session using the parser and evaluator of a pipeline
+    pub fn new(pipeline: Pipeline, color: bool) -> Repl {
+        let options = pipeline.options().clone();
+        Repl {
+            lex: Lexer::new(),
+            parse: pipeline.parser(),
+            evaluate: pipeline.into_evaluator(),
+            dynamic: options.dynamic,
+            typecheck: options.typecheck,
+            systemf: options.systemf,
+            statements: Vec::new(),
+            color
+        }
+    }
+    // Read, evaluate, and print until end of input
+    pub fn run(&mut self) {
+        let stdin = io::stdin();
+        // Buffer for multi-line input
+        let mut buffer = String::new();
+        loop {
+            // Print prompt
+            print!("{}", if buffer.is_empty() { PROMPT } else { CONTINUE_PROMPT });
+            let _ = io::stdout().flush();
+            // Read line, stop on end of input
+            let mut line = String::new();
+            match stdin.lock().read_line(&mut line) {
+                Ok(0) => { println!(); break },
+                Ok(_) => (),
+                Err(_) => {
+                    eprintln!("stdin error");
+                    break
+                }
+            }
+            // Commands are only recognized at the start of an input
+            if buffer.is_empty() && line.trim_start().starts_with(':') {
+                if !self.command(line.trim()) { break }
+                continue
+            }
+            // Skip empty input
+            if buffer.is_empty() && line.trim().is_empty() { continue }
+            // A blank line forces evaluation of incomplete input
+            let force = line.trim().is_empty();
+            buffer.push_str(&line);
+            // Attempt to evaluate buffer
+            match self.eval_input(&buffer, force) {
+                Outcome::Incomplete => continue,
+                Outcome::Done(result) => {
+                    self.report(result, &buffer, "<repl>");
+                    buffer.clear()
+                }
+            }
+        }
+    }
+    // Run a command, return false if the REPL should exit
+    fn command(&mut self, line: &str) -> bool {
+        // Split command from its argument
+        let (cmd, arg) = match line.split_once(char::is_whitespace) {
+            Some((c, a)) => (c, a.trim()),
+            None => (line, "")
+        };
+        match cmd {
+            ":env" => {
+                println!("{}", printing::environment_to_string(self.evaluate.env()))
+            },
+            ":reset" => {
+                self.evaluate.reset();
+                self.parse.forget_declarations(0);
+                self.statements.clear()
+            },
+            ":load" => {
+                if arg.is_empty() { eprintln!("usage: :load <file>") }
+                else { self.load(arg) }
+            },
+            ":help" => println!("{}", HELP),
+            ":quit" | ":q" => return false,
+            _ => eprintln!("Unknown command '{}', try :help", cmd)
+        };
+        true
+    }
+    // Evaluate a program file in the current environment
+    pub fn load(&mut self, fname: &str) {
+        match fs::read_to_string(fname) {
+            Ok(s) => match self.eval_input(&s, true) {
+                Outcome::Done(result) => self.report(result, &s, fname),
+                Outcome::Incomplete => ()
+            },
+            Err(_) => eprintln!("file error")
+        }
+    }
+    // Lex, parse, and evaluate input, declarations are only kept if it succeeds
+    fn eval_input(&mut self, input: &str, force: bool) -> Outcome {
+        let declarations = self.parse.declarations();
+        let outcome = self.eval_declaring(input, force);
+        if !matches!(outcome, Outcome::Done(Ok(_))) { self.parse.forget_declarations(declarations) }
+        outcome
+    }
+    // Lex, parse, and evaluate input in the current environment
+    fn eval_declaring(&mut self, input: &str, force: bool) -> Outcome {
+        // Generate lexer output
+        let lexer_out = match self.lex.generate(input) {
+            Ok(s) => s,
+            Err(e) => return Outcome::Done(Err(e))
+        };
+        // Generate parser output, wait for more input if parser ran out of tokens
+        let parser_out = match self.parse.parse_repl(lexer_out) {
+            Ok(s) => s,
+            Err(_) if !force && self.parse.exhausted() => return Outcome::Incomplete,
+            Err(e) => return Outcome::Done(Err(e))
+        };
+        // Check types together with earlier statements, System F input is then erased
+        if self.typecheck || self.systemf {
+            if let Err(e) = self.check(&parser_out) { return Outcome::Done(Err(e)) }
+        }
+        let stmts = parser_out.0.clone();
+        let parser_out = if self.systemf {
+            (parser_out.0.iter().map(|(id, e)| (id.clone(), systemf::erase(e))).collect(), parser_out.1.map(|e| systemf::erase(&e)))
+        } else {
+            parser_out
+        };
+        // Evaluate parser output, keeping statements for later checks once they are in the environment
+        let result = self.evaluate.eval_repl(parser_out, self.dynamic);
+        if result.is_ok() { self.statements.extend(stmts) }
+        Outcome::Done(result)
+    }
+    // Check input after the statements accepted so far
+    fn check(&self, input: &(Vec<Statement>, Option<Expression>)) -> Result<(), Error> {
+        let mut stmts = self.statements.clone();
+        stmts.extend(input.0.iter().cloned());
+        let prog = (stmts, input.1.clone().unwrap_or(Expression::ValExpr(Value::Unit)));
+        if self.systemf {
+            systemf::Checker::new().check_program(&prog)?;
+        } else {
+            TypeChecker::new().check_program(&prog)?;
+        }
+        Ok(())
+    }
+    // Print result of an evaluation, rendering errors against their source
+    fn report(&self, result: Result<Option<Expression>, Error>, source: &str, fname: &str) {
+        match result {
+            Ok(Some(ex)) => println!("{}", printing::grouped_expression_to_string(&ex, false)),
+            Ok(None) => (),
+            Err(e) => eprint!("{}", diagnostic::render(&e, source, fname, self.color))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ltk::Options;
+
+    fn session(typecheck: bool) -> Repl {
+        Repl::new(Options { typecheck, ..Options::default() }.build().unwrap(), false)
+    }
+
+    // Printed result of complete input
+    fn eval(repl: &mut Repl, input: &str) -> Result<Option<String>, Error> {
+        match repl.eval_input(input, false) {
+            Outcome::Done(result) => result.map(|e| e.map(|ex| printing::grouped_expression_to_string(&ex, false))),
+            Outcome::Incomplete => panic!("input '{}' is incomplete", input)
+        }
+    }
+
+    #[test]
+    fn statements_persist_across_inputs() {
+        let mut repl = session(false);
+        assert_eq!(eval(&mut repl, r"let f = \x. x + 1 in").unwrap(), None);
+        assert_eq!(eval(&mut repl, "let y = f 1 in f y").unwrap(), Some("3".to_string()));
+        assert_eq!(eval(&mut repl, "y").unwrap(), Some("2".to_string()));
+    }
+
+    #[test]
+    fn incomplete_input_waits_unless_forced() {
+        let mut repl = session(false);
+        assert!(matches!(repl.eval_input("let x = 1", false), Outcome::Incomplete));
+        assert!(matches!(repl.eval_input("let x = 1", true), Outcome::Done(Err(_))));
+        assert!(matches!(repl.eval_input("(1 + ", false), Outcome::Incomplete));
+    }
+
+    #[test]
+    fn failed_input_keeps_no_statements_or_types() {
+        let mut repl = session(true);
+        eval(&mut repl, "let x = 1 in").unwrap();
+        assert!(eval(&mut repl, "let x = true in x + 1").is_err());
+        assert!(eval(&mut repl, "type t = A in 1 + true").is_err());
+        // Earlier statement keeps its type, the failed declaration is gone
+        assert_eq!(eval(&mut repl, "x + 1").unwrap(), Some("2".to_string()));
+        assert!(matches!(eval(&mut repl, "A"), Err(Error::UnboundError(_, _))));
+    }
+
+    #[test]
+    fn reset_forgets_statements_and_declarations() {
+        let mut repl = session(true);
+        eval(&mut repl, "type t = A in let x = 1 in").unwrap();
+        assert!(repl.command(":reset"));
+        assert!(matches!(eval(&mut repl, "x"), Err(Error::UnboundError(_, _))));
+        assert!(matches!(eval(&mut repl, "A"), Err(Error::UnboundError(_, _))));
+        assert!(!repl.command(":quit"));
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::HashSet;
+use crate::types::ast::*;
+
+// Expression without surrounding source spans
+pub fn unspan(e: &Expression) -> &Expression {
+    match e {
+        Expression::SpanExpr(_, ex) => unspan(ex.as_ref()),
+        _ => e
+    }
+}
+
+// Subexpressions of an expression that does not bind variables
+fn children(e: &Expression) -> Vec<&Expression> {
+    match e {
+        Expression::UopExpr(_, e1) => vec![e1.as_ref()],
+        Expression::BopExpr(_, e1, e2) => vec![e1.as_ref(), e2.as_ref()],
+        Expression::ApplicationExpr(alist) => alist.iter().collect(),
+        Expression::IfExpr(c, t, e) => vec![c.as_ref(), t.as_ref(), e.as_ref()],
+        Expression::FixExpr(flist, _) => flist.iter().collect(),
+        Expression::EnvExpr(_, ex) => vec![ex.as_ref()],
+        Expression::SpanExpr(_, ex) => vec![ex.as_ref()],
+        Expression::TypeFuncExpr(_, ex) | Expression::TypeAppExpr(ex, _) | Expression::DeclExpr(_, ex) | Expression::ProjExpr(ex, _, _) => vec![ex.as_ref()],
+        Expression::TupleExpr(elist) | Expression::ValExpr(Value::Tuple(elist)) => elist.iter().collect(),
+        Expression::ListExpr(elist) | Expression::ValExpr(Value::List(elist)) => elist.iter().collect(),
+        Expression::ConstructExpr(_, _, elist) | Expression::ValExpr(Value::Data(_, _, elist)) => elist.iter().collect(),
+        _ => Vec::new()
+    }
+}
+
+// Collect variables that are not bound within the expression
+fn collect_free(e: &Expression, bound: &mut Vec<String>, out: &mut HashSet<String>) {
+    match e {
+        Expression::ValExpr(Value::Identifier(x)) => {
+            if !bound.contains(x) { out.insert(x.clone()); }
+        },
+        Expression::FuncExpr(params, _, body) | Expression::ValExpr(Value::Closure(params, body, _)) => {
+            // Parameters are bound in body
+            let n = bound.len();
+            bound.extend(params.iter().flat_map(Pattern::vars).cloned());
+            collect_free(body.as_ref(), bound, out);
+            bound.truncate(n);
+        },
+        Expression::LetExpr(id, e1, body) => {
+            // Identifier is bound in body only
+            collect_free(e1.as_ref(), bound, out);
+            let n = bound.len();
+            bound.extend(id.vars().into_iter().cloned());
+            collect_free(body.as_ref(), bound, out);
+            bound.truncate(n);
+        },
+        Expression::MatchExpr(e1, cases) => {
+            // Case variables are bound in their body
+            collect_free(e1.as_ref(), bound, out);
+            for case in cases {
+                let n = bound.len();
+                bound.extend(case.vars.iter().flatten().cloned());
+                collect_free(&case.body, bound, out);
+                bound.truncate(n);
+            }
+        },
+        _ => for ex in children(e) { collect_free(ex, bound, out) }
+    }
+}
+
+// Variables occurring free in expression
+pub fn free_vars(e: &Expression) -> HashSet<String> {
+    let mut out = HashSet::new();
+    collect_free(e, &mut Vec::new(), &mut out);
+    out
+}
+
+// Collect every variable name in the expression, free or bound
+fn collect_all(e: &Expression, out: &mut HashSet<String>) {
+    match e {
+        Expression::ValExpr(Value::Identifier(x)) => { out.insert(x.clone()); },
+        Expression::FuncExpr(params, _, body) | Expression::ValExpr(Value::Closure(params, body, _)) => {
+            out.extend(params.iter().flat_map(Pattern::vars).cloned());
+            collect_all(body.as_ref(), out);
+        },
+        Expression::LetExpr(id, e1, body) => {
+            out.extend(id.vars().into_iter().cloned());
+            collect_all(e1.as_ref(), out);
+            collect_all(body.as_ref(), out);
+        },
+        Expression::MatchExpr(e1, cases) => {
+            collect_all(e1.as_ref(), out);
+            for case in cases {
+                out.extend(case.vars.iter().flatten().cloned());
+                collect_all(&case.body, out);
+            }
+        },
+        _ => for ex in children(e) { collect_all(ex, out) }
+    }
+}
+
+// Variant of name that is not in avoid, adding primes
+pub fn fresh(name: &str, avoid: &HashSet<String>) -> String {
+    let mut new = name.to_string() + "'";
+    while avoid.contains(&new) { new.push('\'') }
+    new
+}
+
+// Replace free occurrences of x in e with v, renaming binders that would capture free variables of v
+pub fn substitute(e: &Expression, x: &str, v: &Expression) -> Expression {
+    substitute_renaming(e, x, v, &mut Vec::new())
+}
+
+// Substitute, recording each renamed binder as old and new name
+pub fn substitute_renaming(e: &Expression, x: &str, v: &Expression, renames: &mut Vec<(String, String)>) -> Expression {
+    let mut sub = Subst { x, v, fv: free_vars(v), renames };
+    sub.expr(e)
+}
+
+// Substitution of v for x
+struct Subst<'a> {
+    x: &'a str,
+    v: &'a Expression,
+    fv: HashSet<String>,
+    renames: &'a mut Vec<(String, String)>
+}
+impl Subst<'_> {
+    fn expr(&mut self, e: &Expression) -> Expression {
+        match e {
+            Expression::ValExpr(Value::Identifier(y)) if y == self.x => self.v.clone(),
+            Expression::FuncExpr(params, types, body) => {
+                let mut params = params.clone();
+                let body = self.binder(params.iter_mut().flat_map(Pattern::idents_mut).collect(), body.as_ref());
+                Expression::FuncExpr(params, types.clone(), Box::new(body))
+            },
+            Expression::LetExpr(id, e1, body) => {
+                let mut id = id.clone();
+                let body = self.binder(id.idents_mut(), body.as_ref());
+                Expression::LetExpr(id, Box::new(self.expr(e1.as_ref())), Box::new(body))
+            },
+            Expression::UopExpr(op, e1) => Expression::UopExpr(op.clone(), Box::new(self.expr(e1.as_ref()))),
+            Expression::BopExpr(op, e1, e2) => Expression::BopExpr(
+                op.clone(),
+                Box::new(self.expr(e1.as_ref())),
+                Box::new(self.expr(e2.as_ref()))
+            ),
+            Expression::ApplicationExpr(alist) => Expression::ApplicationExpr(alist.iter().map(|ex| self.expr(ex)).collect()),
+            Expression::IfExpr(c, t, f) => Expression::IfExpr(
+                Box::new(self.expr(c.as_ref())),
+                Box::new(self.expr(t.as_ref())),
+                Box::new(self.expr(f.as_ref()))
+            ),
+            Expression::FixExpr(flist, i) => Expression::FixExpr(flist.iter().map(|ex| self.expr(ex)).collect(), *i),
+            Expression::TupleExpr(elist) => Expression::TupleExpr(elist.iter().map(|ex| self.expr(ex)).collect()),
+            Expression::ListExpr(elist) => Expression::ListExpr(elist.iter().map(|ex| self.expr(ex)).collect()),
+            Expression::ProjExpr(ex, i, n) => Expression::ProjExpr(Box::new(self.expr(ex.as_ref())), *i, *n),
+            Expression::ConstructExpr(d, i, elist) => Expression::ConstructExpr(d.clone(), *i, elist.iter().map(|ex| self.expr(ex)).collect()),
+            Expression::MatchExpr(ex, cases) => Expression::MatchExpr(
+                Box::new(self.expr(ex.as_ref())),
+                cases.iter().map(|case| {
+                    let mut vars = case.vars.clone();
+                    let body = self.binder(vars.iter_mut().collect(), &case.body);
+                    Case { constructor: case.constructor.clone(), vars, body }
+                }).collect()
+            ),
+            Expression::TypeFuncExpr(a, ex) => Expression::TypeFuncExpr(a.clone(), Box::new(self.expr(ex.as_ref()))),
+            Expression::TypeAppExpr(ex, t) => Expression::TypeAppExpr(Box::new(self.expr(ex.as_ref())), t.clone()),
+            Expression::DeclExpr(d, ex) => Expression::DeclExpr(d.clone(), Box::new(self.expr(ex.as_ref()))),
+            // Replaced variable keeps the span of the value so spans do not pile up
+            Expression::SpanExpr(_, ex) if matches!(unspan(ex), Expression::ValExpr(Value::Identifier(y)) if y == self.x) => self.v.clone(),
+            Expression::SpanExpr(span, ex) => Expression::SpanExpr(*span, Box::new(self.expr(ex.as_ref()))),
+            // Values, errors, and environment machine nodes have no free variables to replace
+            _ => e.clone()
+        }
+    }
+    // Substitute in body under binders, renaming the binders in place
+    fn binder(&mut self, mut params: Vec<&mut Ident>, body: &Expression) -> Expression {
+        let x = self.x;
+        // Nothing to replace if x is shadowed or does not occur in body
+        if params.iter().any(|p| p.as_deref() == Some(x)) || !free_vars(body).contains(x) {
+            return body.clone()
+        }
+        // Names a renamed binder must not clash with
+        let mut avoid = HashSet::new();
+        collect_all(body, &mut avoid);
+        avoid.extend(self.fv.iter().cloned());
+        avoid.extend(params.iter().filter_map(|p| (**p).clone()));
+        avoid.insert(x.to_string());
+        // Rename binders that would capture free variables of v
+        let mut body = body.clone();
+        for i in 0..params.len() {
+            let name = match &*params[i] {
+                Some(name) if self.fv.contains(name) => name.clone(),
+                _ => continue
+            };
+            let new = fresh(&name, &avoid);
+            avoid.insert(new.clone());
+            // Body refers to the last parameter with a given name
+            if !params[i + 1..].iter().any(|p| p.as_deref() == Some(name.as_str())) {
+                body = substitute(&body, &name, &Expression::ValExpr(Value::Identifier(new.clone())));
+            }
+            self.renames.push((name, new.clone()));
+            *params[i] = Some(new);
+        }
+        self.expr(&body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, printing::grouped_expression_to_string};
+
+    // Body of a program, statements need parentheses to be part of it
+    fn parse(src: &str) -> Expression {
+        let tokens = Lexer::new().generate(src).unwrap();
+        Parser::new(false, false).parse_program(tokens).unwrap().1
+    }
+
+    fn var(x: &str) -> Expression {
+        Expression::ValExpr(Value::Identifier(x.to_string()))
+    }
+
+    #[test]
+    fn renames_binder_that_would_capture() {
+        let mut renames = Vec::new();
+        let e = substitute_renaming(&parse(r"\y. x"), "x", &var("y"), &mut renames);
+        assert_eq!(grouped_expression_to_string(&e, false), r"(\y'. y)");
+        assert_eq!(renames, vec![("y".to_string(), "y'".to_string())]);
+    }
+
+    #[test]
+    fn renamed_binder_avoids_names_in_body() {
+        let e = substitute(&parse(r"\y. x y'"), "x", &var("y"));
+        assert_eq!(grouped_expression_to_string(&e, false), r"(\y''. y y')");
+    }
+
+    #[test]
+    fn shadowed_variable_is_not_replaced() {
+        let e = substitute(&parse(r"\x. x"), "x", &var("y"));
+        assert_eq!(grouped_expression_to_string(&e, false), r"(\x. x)");
+    }
+
+    #[test]
+    fn let_binds_like_lambda() {
+        let e = substitute(&parse("(let y = x in x y)"), "x", &var("y"));
+        assert_eq!(grouped_expression_to_string(&e, false), "(let y' = y in y y')");
+    }
+
+    #[test]
+    fn match_binds_like_lambda() {
+        let e = substitute(&parse("(type t = A int in match A 1 with A y -> x | z -> z)"), "x", &var("y"));
+        assert_eq!(grouped_expression_to_string(&e, false), "(type t = A int in (match A 1 with | A y' -> y | z -> z))");
+    }
+
+    #[test]
+    fn free_variables_exclude_bound_ones() {
+        let mut free: Vec<String> = free_vars(&parse(r"\x. x y (let z = w in z)")).into_iter().collect();
+        free.sort();
+        assert_eq!(free, vec!["w".to_string(), "y".to_string()]);
+    }
+}
@@ -0,0 +1,572 @@
+use crate::error::Error;
+use crate::parser::declared;
+use crate::printing::pattern_to_string;
+use crate::typechecker::ProgramTypes;
+use crate::types::{ast::*, token::Span};
+
+// Type variables occurring free in a type
+fn free_named(t: &Type, out: &mut Vec<String>) {
+    match t {
+        Type::Named(a) if !out.contains(a) => out.push(a.clone()),
+        Type::Arrow(t1, t2) => {
+            free_named(t1, out);
+            free_named(t2, out);
+        },
+        Type::Tuple(tlist) => for t1 in tlist.iter() { free_named(t1, out) },
+        Type::List(t1) => free_named(t1, out),
+        Type::Data(_, tlist) => for t1 in tlist.iter() { free_named(t1, out) },
+        Type::Forall(a, t1) => {
+            let mut inner = Vec::new();
+            free_named(t1, &mut inner);
+            for b in inner { if &b != a && !out.contains(&b) { out.push(b) } }
+        },
+        _ => ()
+    }
+}
+
+// Replace free occurrences of type variable a in t with s, renaming quantifiers that would capture variables of s
+pub fn substitute_type(t: &Type, a: &str, s: &Type) -> Type {
+    match t {
+        Type::Named(b) if b == a => s.clone(),
+        Type::Arrow(t1, t2) => Type::Arrow(Box::new(substitute_type(t1, a, s)), Box::new(substitute_type(t2, a, s))),
+        Type::Tuple(tlist) => Type::Tuple(tlist.iter().map(|t1| substitute_type(t1, a, s)).collect()),
+        Type::List(t1) => Type::List(Box::new(substitute_type(t1, a, s))),
+        Type::Data(name, tlist) => Type::Data(name.clone(), tlist.iter().map(|t1| substitute_type(t1, a, s)).collect()),
+        // Shadowed
+        Type::Forall(b, _) if b == a => t.clone(),
+        Type::Forall(b, t1) => {
+            let mut avoid = Vec::new();
+            free_named(s, &mut avoid);
+            if !avoid.contains(b) { return Type::Forall(b.clone(), Box::new(substitute_type(t1, a, s))) }
+            // Rename quantified variable away from variables of s and of the body
+            free_named(t1, &mut avoid);
+            let mut new = b.clone() + "'";
+            while avoid.contains(&new) || new == a { new.push('\'') }
+            let t1 = substitute_type(t1, b, &Type::Named(new.clone()));
+            Type::Forall(new, Box::new(substitute_type(&t1, a, s)))
+        },
+        _ => t.clone()
+    }
+}
+
+// Field types of a constructor with the parameters of its data type replaced by arguments
+pub fn instantiate_fields(d: &DataType, i: usize, args: &[Type]) -> Vec<Type> {
+    // Parameters are first renamed apart from the arguments, so arguments mentioning them are not replaced again
+    let mut avoid = d.params.clone();
+    for t in args.iter().chain(d.constructors[i].1.iter()) { free_named(t, &mut avoid) }
+    let renamed: Vec<String> = d.params.iter().map(|p| {
+        let mut name = p.clone() + "'";
+        while avoid.contains(&name) { name.push('\'') }
+        avoid.push(name.clone());
+        name
+    }).collect();
+    d.constructors[i].1.iter().map(|t| {
+        let t = d.params.iter().zip(renamed.iter()).fold(t.clone(), |acc, (p, r)| substitute_type(&acc, p, &Type::Named(r.clone())));
+        renamed.iter().zip(args.iter()).fold(t, |acc, (r, s)| substitute_type(&acc, r, s))
+    }).collect()
+}
+
+// Data types used anywhere in an expression
+fn collect_data(e: &Expression, out: &mut Vec<DataType>) {
+    let mut add = |d: &DataType| if !out.iter().any(|o| o.name == d.name) { out.push(d.clone()) };
+    match e {
+        Expression::ConstructExpr(d, _, _) | Expression::ValExpr(Value::Data(d, _, _)) | Expression::DeclExpr(d, _) => add(d),
+        Expression::MatchExpr(_, cases) => for (d, _) in cases.iter().filter_map(|c| c.constructor.as_ref()) { add(d) },
+        _ => ()
+    }
+    match e {
+        Expression::ConstructExpr(_, _, elist) | Expression::ListExpr(elist) | Expression::TupleExpr(elist) | Expression::ApplicationExpr(elist) | Expression::FixExpr(elist, _) => {
+            for ex in elist.iter() { collect_data(ex, out) }
+        },
+        Expression::ValExpr(Value::Data(_, _, elist) | Value::Tuple(elist) | Value::List(elist)) => for ex in elist.iter() { collect_data(ex, out) },
+        Expression::ValExpr(Value::Closure(_, ex, _)) => collect_data(ex, out),
+        Expression::MatchExpr(ex, cases) => {
+            collect_data(ex, out);
+            for case in cases.iter() { collect_data(&case.body, out) }
+        },
+        Expression::UopExpr(_, ex) | Expression::ProjExpr(ex, _, _) | Expression::FuncExpr(_, _, ex) | Expression::TypeFuncExpr(_, ex) | Expression::TypeAppExpr(ex, _) | Expression::DeclExpr(_, ex) | Expression::EnvExpr(_, ex) | Expression::SpanExpr(_, ex) => collect_data(ex, out),
+        Expression::BopExpr(_, e1, e2) | Expression::LetExpr(_, e1, e2) => {
+            collect_data(e1, out);
+            collect_data(e2, out);
+        },
+        Expression::IfExpr(c, t, f) => {
+            collect_data(c, out);
+            collect_data(t, out);
+            collect_data(f, out);
+        },
+        Expression::ThunkExpr(cell) => collect_data(&cell.borrow(), out),
+        _ => ()
+    }
+}
+
+// Whether a field type contains a function, directly or through a data type already known to
+fn has_function(t: &Type, functional: &[String]) -> bool {
+    match t {
+        Type::Arrow(_, _) | Type::Forall(_, _) => true,
+        Type::Tuple(tlist) => tlist.iter().any(|t1| has_function(t1, functional)),
+        Type::List(t1) => has_function(t1, functional),
+        Type::Data(name, tlist) => functional.contains(name) || tlist.iter().any(|t1| has_function(t1, functional)),
+        _ => false
+    }
+}
+
+// Names of the data types of a program with a field containing a function, whose values cannot be compared
+pub fn functional_data(prog: &Program) -> Vec<String> {
+    let mut data = Vec::new();
+    for (_, e) in prog.0.iter() { collect_data(e, &mut data) }
+    collect_data(&prog.1, &mut data);
+    // Add data types until none has a field containing one found so far
+    let mut functional: Vec<String> = Vec::new();
+    loop {
+        let found: Vec<String> = data.iter()
+            .filter(|d| !functional.contains(&d.name) && d.constructors.iter().any(|c| c.1.iter().any(|t| has_function(t, &functional))))
+            .map(|d| d.name.clone())
+            .collect();
+        if found.is_empty() { return functional }
+        functional.extend(found);
+    }
+}
+
+// Bind parameters of a data type by matching the type of a field against the type found for it
+fn bind_params(field: &Type, found: &Type, params: &[String], binding: &mut [Option<Type>]) {
+    match (field, found) {
+        (Type::Named(a), _) => if let Some(j) = params.iter().position(|p| p == a) {
+            if binding[j].is_none() { binding[j] = Some(found.clone()) }
+        },
+        (Type::Arrow(a1, r1), Type::Arrow(a2, r2)) => {
+            bind_params(a1, a2, params, binding);
+            bind_params(r1, r2, params, binding);
+        },
+        (Type::Tuple(l1), Type::Tuple(l2)) => {
+            for (c1, c2) in l1.iter().zip(l2.iter()) { bind_params(c1, c2, params, binding) }
+        },
+        (Type::Data(_, l1), Type::Data(_, l2)) => {
+            for (c1, c2) in l1.iter().zip(l2.iter()) { bind_params(c1, c2, params, binding) }
+        },
+        (Type::List(e1), Type::List(e2)) => bind_params(e1, e2, params, binding),
+        _ => ()
+    }
+}
+
+// Equality up to renaming of quantified variables, with pairs of quantified variables in scope
+fn type_equal(t1: &Type, t2: &Type, pairs: &mut Vec<(String, String)>) -> bool {
+    match (t1, t2) {
+        // Innermost quantifier binding either name decides
+        (Type::Named(a), Type::Named(b)) => match pairs.iter().rev().find(|p| &p.0 == a || &p.1 == b) {
+            Some(p) => &p.0 == a && &p.1 == b,
+            None => a == b
+        },
+        (Type::Forall(a, b1), Type::Forall(b, b2)) => {
+            pairs.push((a.clone(), b.clone()));
+            let equal = type_equal(b1, b2, pairs);
+            pairs.pop();
+            equal
+        },
+        (Type::Arrow(a1, r1), Type::Arrow(a2, r2)) => type_equal(a1, a2, pairs) && type_equal(r1, r2, pairs),
+        (Type::Tuple(l1), Type::Tuple(l2)) => l1.len() == l2.len() && l1.iter().zip(l2.iter()).all(|(c1, c2)| type_equal(c1, c2, pairs)),
+        (Type::List(e1), Type::List(e2)) => type_equal(e1, e2, pairs),
+        (Type::Data(n1, l1), Type::Data(n2, l2)) => n1 == n2 && l1.len() == l2.len() && l1.iter().zip(l2.iter()).all(|(c1, c2)| type_equal(c1, c2, pairs)),
+        (Type::Int, Type::Int) | (Type::Bool, Type::Bool) | (Type::Unit, Type::Unit) => true,
+        _ => false
+    }
+}
+
+// Checker for explicitly typed System F, every parameter is annotated
+pub struct Checker {
+    // Variables in scope and their types, innermost last
+    env: Vec<(String, Type)>,
+    // Data types whose values cannot be compared
+    functional: Vec<String>,
+    span: Span
+}
+impl Default for Checker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Checker {
+    pub fn new() -> Checker {
+        Checker {
+            env: Vec::new(),
+            functional: Vec::new(),
+            span: ((0, 0), (0, 0))
+        }
+    }
+    // Span of a subexpression, or of the enclosing expression if it has none
+    fn span_of(&self, e: &Expression) -> Span {
+        match e {
+            Expression::SpanExpr(s, _) => *s,
+            _ => self.span
+        }
+    }
+    // Found type must equal expected type
+    fn expect(&self, expected: &Type, found: &Type, span: Span) -> Result<(), Error> {
+        if type_equal(expected, found, &mut Vec::new()) { Ok(()) } else { Err(Error::MismatchError(expected.clone(), found.clone(), span)) }
+    }
+    // Whether values of a type can be compared, type variables may stand for functions
+    fn comparable(&self, t: &Type) -> bool {
+        match t {
+            Type::Arrow(_, _) | Type::Forall(_, _) | Type::Named(_) => false,
+            Type::Tuple(tlist) => tlist.iter().all(|t1| self.comparable(t1)),
+            Type::List(t1) => self.comparable(t1),
+            Type::Data(name, tlist) => !self.functional.contains(name) && tlist.iter().all(|t1| self.comparable(t1)),
+            _ => true
+        }
+    }
+    // Check subexpression against a type
+    fn check(&mut self, e: &Expression, expected: &Type) -> Result<(), Error> {
+        let found = self.infer(e)?;
+        self.expect(expected, &found, self.span_of(e))
+    }
+    // List whose elements all have the type of the first, the empty list is polymorphic
+    fn list(&mut self, elist: &[Expression]) -> Result<Type, Error> {
+        let t = match elist.first() {
+            Some(ex) => self.infer(ex)?,
+            None => return Ok(Type::Forall("a".to_string(), Box::new(Type::List(Box::new(Type::Named("a".to_string()))))))
+        };
+        for ex in elist[1..].iter() { self.check(ex, &t)? };
+        Ok(Type::List(Box::new(t)))
+    }
+    // Constructed data, parameters not determined by the fields stay polymorphic
+    fn construct(&mut self, d: &DataType, i: usize, elist: &[Expression]) -> Result<Type, Error> {
+        let fields = &d.constructors[i].1;
+        let mut binding = vec![None; d.params.len()];
+        let mut found = Vec::new();
+        for (field, ex) in fields.iter().zip(elist.iter()) {
+            let t = self.infer(ex)?;
+            bind_params(field, &t, &d.params, &mut binding);
+            found.push(t);
+        }
+        // Undetermined parameters stand for themselves and are quantified
+        let args: Vec<Type> = binding.iter().zip(d.params.iter()).map(|(b, p)| b.clone().unwrap_or(Type::Named(p.clone()))).collect();
+        for ((field, t), ex) in instantiate_fields(d, i, &args).iter().zip(found.iter()).zip(elist.iter()) {
+            self.expect(field, t, self.span_of(ex))?;
+        }
+        let result = Type::Data(d.name.clone(), args.into());
+        Ok(binding.iter().zip(d.params.iter()).rev().fold(result, |acc, (b, p)| match b {
+            Some(_) => acc,
+            None => Type::Forall(p.clone(), Box::new(acc))
+        }))
+    }
+    // Cases bind the fields of the constructor they match, and all bodies have the same type
+    fn cases(&mut self, e1: &Expression, cases: &[Case]) -> Result<Type, Error> {
+        let t1 = self.infer(e1)?;
+        let mut result: Option<Type> = None;
+        for case in cases.iter() {
+            let n = self.env.len();
+            let vtypes = match &case.constructor {
+                Some((d, i)) => match &t1 {
+                    Type::Data(name, args) if name == &d.name && args.len() == d.params.len() => instantiate_fields(d, *i, args),
+                    found => {
+                        let expected = Type::Data(d.name.clone(), d.params.iter().map(|p| Type::Named(p.clone())).collect());
+                        return Err(Error::MismatchError(expected, found.clone(), self.span_of(e1)))
+                    }
+                },
+                None => vec![t1.clone()]
+            };
+            for (var, t) in case.vars.iter().zip(vtypes) {
+                if let Some(name) = var { self.env.push((name.clone(), t)) };
+            }
+            let tb = self.infer(&case.body);
+            self.env.truncate(n);
+            let tb = tb?;
+            match &result {
+                Some(t) => self.expect(t, &tb, self.span_of(&case.body))?,
+                None => result = Some(tb)
+            }
+        }
+        Ok(result.unwrap_or(Type::Unit))
+    }
+    // Variables of a pattern matching a value of the given type, a tuple pattern needs a tuple type of its size
+    fn bind(&self, p: &Pattern, t: &Type, span: Span) -> Result<Vec<(String, Type)>, Error> {
+        match (p, t) {
+            (Pattern::Var(id), _) => Ok(id.iter().map(|x| (x.clone(), t.clone())).collect()),
+            (Pattern::Tuple(plist), Type::Tuple(tlist)) if plist.len() == tlist.len() => {
+                let mut vars = Vec::new();
+                for (q, tq) in plist.iter().zip(tlist.iter()) { vars.extend(self.bind(q, tq, span)?) }
+                Ok(vars)
+            },
+            _ => Err(Error::ExpectedTypeError("a tuple of the size of the pattern", t.clone(), span))
+        }
+    }
+    fn infer(&mut self, e: &Expression) -> Result<Type, Error> {
+        match e {
+            Expression::ValExpr(v) => match v {
+                Value::Number(_) => Ok(Type::Int),
+                Value::Boolean(_) => Ok(Type::Bool),
+                Value::Unit => Ok(Type::Unit),
+                // Innermost binding of variable
+                Value::Identifier(x) => match self.env.iter().rev().find(|b| &b.0 == x) {
+                    Some(b) => Ok(b.1.clone()),
+                    None => Err(Error::UnboundError(x.clone(), self.span))
+                },
+                // Closures only exist at runtime and carry no annotations
+                Value::Closure(params, _, _) => Err(Error::AnnotationError(pattern_to_string(&params[0]), self.span)),
+                Value::Tuple(elist) => Ok(Type::Tuple(elist.iter().map(|ex| self.infer(ex)).collect::<Result<_, _>>()?)),
+                Value::List(elist) => self.list(elist),
+                Value::Data(d, i, elist) => self.construct(d, *i, elist)
+            },
+            Expression::ListExpr(elist) => self.list(elist),
+            Expression::TupleExpr(elist) => Ok(Type::Tuple(elist.iter().map(|ex| self.infer(ex)).collect::<Result<_, _>>()?)),
+            Expression::ConstructExpr(d, i, elist) => self.construct(d, *i, elist),
+            Expression::MatchExpr(e1, cases) => self.cases(e1.as_ref(), cases),
+            Expression::ProjExpr(ex, i, n) => match self.infer(ex.as_ref())? {
+                // Component of a tuple type, of the size known from the projection if there is one
+                Type::Tuple(tlist) if *i < tlist.len() && n.is_none_or(|n| n == tlist.len()) => Ok(tlist[*i].clone()),
+                found @ Type::Tuple(_) if n.is_some() => Err(Error::ExpectedTypeError("a tuple of the size the projection expects", found, self.span_of(ex.as_ref()))),
+                found @ Type::Tuple(_) => Err(Error::ExpectedTypeError("a tuple with a component at this index", found, self.span_of(ex.as_ref()))),
+                found => Err(Error::ExpectedTypeError("a tuple", found, self.span_of(ex.as_ref())))
+            },
+            Expression::FuncExpr(params, types, body) => {
+                let n = self.env.len();
+                let mut ptypes = Vec::new();
+                for (i, p) in params.iter().enumerate() {
+                    let t = match types.get(i) {
+                        Some(Some(t)) => t.clone(),
+                        _ => {
+                            self.env.truncate(n);
+                            return Err(Error::AnnotationError(pattern_to_string(p), self.span))
+                        }
+                    };
+                    match self.bind(p, &t, self.span) {
+                        Ok(vars) => self.env.extend(vars),
+                        Err(err) => {
+                            self.env.truncate(n);
+                            return Err(err)
+                        }
+                    };
+                    ptypes.push(t);
+                }
+                let result = self.infer(body.as_ref());
+                self.env.truncate(n);
+                Ok(ptypes.into_iter().rev().fold(result?, |acc, t| Type::Arrow(Box::new(t), Box::new(acc))))
+            },
+            Expression::ApplicationExpr(alist) => {
+                let mut tf = self.infer(&alist[0])?;
+                for arg in alist[1..].iter() {
+                    tf = match tf {
+                        // Argument must match parameter
+                        Type::Arrow(p, r) => {
+                            self.check(arg, &p)?;
+                            *r
+                        },
+                        t => return Err(Error::ExpectedTypeError("a function", t, self.span_of(&alist[0])))
+                    }
+                }
+                Ok(tf)
+            },
+            Expression::TypeFuncExpr(a, body) => Ok(Type::Forall(a.clone(), Box::new(self.infer(body.as_ref())?))),
+            Expression::TypeAppExpr(ex, t) => match self.infer(ex.as_ref())? {
+                // Instantiate quantified variable with type argument
+                Type::Forall(a, body) => Ok(substitute_type(&body, &a, t)),
+                found => Err(Error::ExpectedTypeError("a polymorphic value", found, self.span_of(ex.as_ref())))
+            },
+            Expression::IfExpr(c, t, f) => {
+                // Condition is a boolean and branches agree
+                self.check(c.as_ref(), &Type::Bool)?;
+                let tt = self.infer(t.as_ref())?;
+                self.check(f.as_ref(), &tt)?;
+                Ok(tt)
+            },
+            Expression::LetExpr(id, e1, body) => {
+                let t1 = self.infer(e1.as_ref())?;
+                // Pattern is bound in body only
+                let n = self.env.len();
+                let vars = self.bind(id, &t1, self.span_of(e1.as_ref()))?;
+                self.env.extend(vars);
+                let result = self.infer(body.as_ref());
+                self.env.truncate(n);
+                result
+            },
+            Expression::FixExpr(flist, i) => {
+                // Each function takes every function of the group and returns its own, so the types of the group come from the first
+                let mut group = Vec::new();
+                for (j, f) in flist.iter().enumerate() {
+                    let mut t = self.infer(f)?;
+                    let mut params = Vec::new();
+                    for _ in flist.iter() {
+                        t = match t {
+                            Type::Arrow(p, r) => {
+                                params.push(*p);
+                                *r
+                            },
+                            found => return Err(Error::ExpectedTypeError("a function of the recursive functions", found, self.span_of(f)))
+                        }
+                    }
+                    if j == 0 { group = params.clone() };
+                    // Parameters are the group, result is this function
+                    for (p, g) in params.iter().zip(group.iter()) { self.expect(g, p, self.span_of(f))? };
+                    self.expect(&group[j], &t, self.span_of(f))?;
+                }
+                Ok(group[*i].clone())
+            },
+            Expression::UopExpr(op, e1) => match op {
+                Uop::NegUop => {
+                    self.check(e1.as_ref(), &Type::Int)?;
+                    Ok(Type::Int)
+                },
+                Uop::NotUop => {
+                    self.check(e1.as_ref(), &Type::Bool)?;
+                    Ok(Type::Bool)
+                },
+                // List operations take a list of any element type
+                _ => match self.infer(e1.as_ref())? {
+                    Type::List(t) => Ok(match op {
+                        Uop::HeadUop => *t,
+                        Uop::TailUop => Type::List(t),
+                        _ => Type::Bool
+                    }),
+                    found => Err(Error::ExpectedTypeError("a list", found, self.span_of(e1.as_ref())))
+                }
+            },
+            Expression::BopExpr(op, e1, e2) => {
+                // Operand and result types, equality compares two values of the type of the first
+                let (t, result) = match op {
+                    Bop::PlusBop | Bop::MinusBop | Bop::TimesBop | Bop::DivBop => (Type::Int, Type::Int),
+                    Bop::GtBop | Bop::GteBop | Bop::LtBop | Bop::LteBop => (Type::Int, Type::Bool),
+                    Bop::EqBop => match self.infer(e1.as_ref())? {
+                        t if self.comparable(&t) => (t, Type::Bool),
+                        found => return Err(Error::ExpectedTypeError("a type whose values can be compared", found, self.span_of(e1.as_ref())))
+                    },
+                    // Element is prepended to a list of its type
+                    Bop::ConsBop => {
+                        let t = Type::List(Box::new(self.infer(e1.as_ref())?));
+                        self.check(e2.as_ref(), &t)?;
+                        return Ok(t)
+                    },
+                    Bop::AndBop | Bop::OrBop | Bop::XorBop => (Type::Bool, Type::Bool)
+                };
+                self.check(e1.as_ref(), &t)?;
+                self.check(e2.as_ref(), &t)?;
+                Ok(result)
+            },
+            Expression::SpanExpr(span, ex) => {
+                // Track innermost span for error reporting
+                let outer_span = std::mem::replace(&mut self.span, *span);
+                let result = self.infer(ex.as_ref());
+                self.span = outer_span;
+                result
+            },
+            Expression::EnvExpr(_, ex) | Expression::DeclExpr(_, ex) => self.infer(ex.as_ref()),
+            Expression::ThunkExpr(cell) => self.infer(&cell.borrow()),
+            // Errors were already reported by the parser
+            Expression::ErrorExpr => Ok(Type::Unit)
+        }
+    }
+    // Check each statement and the body, giving their types
+    pub fn check_program(&mut self, prog: &Program) -> Result<ProgramTypes, Error> {
+        self.env.clear();
+        self.functional = functional_data(prog);
+        let mut stmts = Vec::new();
+        // Declarations have no type
+        for (id, e) in prog.0.iter().filter(|s| declared(s).is_none()) {
+            let t = self.infer(e)?;
+            let vars = self.bind(id, &t, self.span_of(e))?;
+            self.env.extend(vars.iter().cloned());
+            // Polymorphism is explicit, so nothing is generalised, and a statement binding no name is listed by its whole type
+            if *id == Pattern::Var(None) { stmts.push((None, Scheme { vars: Vec::new(), body: t })) };
+            stmts.extend(vars.into_iter().map(|(x, t)| (Some(x), Scheme { vars: Vec::new(), body: t })));
+        }
+        Ok((stmts, self.infer(&prog.1)?))
+    }
+}
+
+// Remove type abstractions, applications, and annotations
+pub fn erase(e: &Expression) -> Expression {
+    let go = |ex: &Expression| Box::new(erase(ex));
+    match e {
+        Expression::TypeFuncExpr(_, ex) | Expression::TypeAppExpr(ex, _) => erase(ex.as_ref()),
+        Expression::TupleExpr(elist) => Expression::TupleExpr(elist.iter().map(erase).collect()),
+        Expression::ListExpr(elist) => Expression::ListExpr(elist.iter().map(erase).collect()),
+        Expression::ProjExpr(ex, i, n) => Expression::ProjExpr(go(ex.as_ref()), *i, *n),
+        Expression::ConstructExpr(d, i, elist) => Expression::ConstructExpr(d.clone(), *i, elist.iter().map(erase).collect()),
+        Expression::MatchExpr(ex, cases) => Expression::MatchExpr(
+            go(ex.as_ref()),
+            cases.iter().map(|case| Case { constructor: case.constructor.clone(), vars: case.vars.clone(), body: erase(&case.body) }).collect()
+        ),
+        Expression::FuncExpr(params, _, body) => Expression::FuncExpr(params.clone(), vec![None; params.len()], go(body.as_ref())),
+        Expression::UopExpr(op, e1) => Expression::UopExpr(op.clone(), go(e1.as_ref())),
+        Expression::BopExpr(op, e1, e2) => Expression::BopExpr(op.clone(), go(e1.as_ref()), go(e2.as_ref())),
+        Expression::ApplicationExpr(alist) => Expression::ApplicationExpr(alist.iter().map(erase).collect()),
+        Expression::IfExpr(c, t, f) => Expression::IfExpr(go(c.as_ref()), go(t.as_ref()), go(f.as_ref())),
+        Expression::FixExpr(flist, i) => Expression::FixExpr(flist.iter().map(erase).collect(), *i),
+        Expression::LetExpr(id, e1, body) => Expression::LetExpr(id.clone(), go(e1.as_ref()), go(body.as_ref())),
+        Expression::SpanExpr(span, ex) => Expression::SpanExpr(*span, go(ex.as_ref())),
+        Expression::DeclExpr(d, ex) => Expression::DeclExpr(d.clone(), go(ex.as_ref())),
+        _ => e.clone()
+    }
+}
+
+// Erase statements and body
+pub fn erase_program(prog: Program) -> Program {
+    (prog.0.iter().map(|(id, e)| (id.clone(), erase(e))).collect(), erase(&prog.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, printing::type_to_string};
+
+    fn named(a: &str) -> Type {
+        Type::Named(a.to_string())
+    }
+
+    fn arrow(t1: Type, t2: Type) -> Type {
+        Type::Arrow(Box::new(t1), Box::new(t2))
+    }
+
+    fn forall(a: &str, t: Type) -> Type {
+        Type::Forall(a.to_string(), Box::new(t))
+    }
+
+    fn check(src: &str) -> Result<Type, Error> {
+        let tokens = Lexer::new().generate(src).unwrap();
+        let mut parse = Parser::new(false, false);
+        parse.set_systemf(true);
+        let prog = parse.parse_program(tokens).unwrap();
+        Checker::new().check_program(&prog).map(|(_, t)| t)
+    }
+
+    #[test]
+    fn substitution_renames_capturing_quantifier() {
+        let t = substitute_type(&forall("b", arrow(named("a"), named("b"))), "a", &named("b"));
+        assert_eq!(type_to_string(&t), "forall b'. b -> b'");
+    }
+
+    #[test]
+    fn substitution_stops_at_shadowing_quantifier() {
+        let t = forall("a", arrow(named("a"), named("a")));
+        assert_eq!(substitute_type(&t, "a", &Type::Int), t);
+        let t = substitute_type(&arrow(named("a"), t), "a", &Type::Int);
+        assert_eq!(type_to_string(&t), "int -> forall a. a -> a");
+    }
+
+    #[test]
+    fn types_equal_up_to_renaming_of_quantifiers() {
+        let t1 = forall("a", arrow(named("a"), named("a")));
+        let t2 = forall("b", arrow(named("b"), named("b")));
+        assert!(type_equal(&t1, &t2, &mut Vec::new()));
+        assert!(!type_equal(&t1, &forall("b", arrow(named("b"), Type::Int)), &mut Vec::new()));
+    }
+
+    #[test]
+    fn type_application_instantiates_quantifier() {
+        assert_eq!(type_to_string(&check(r"/\a. \x: a. x").unwrap()), "forall a. a -> a");
+        assert_eq!(type_to_string(&check(r"(/\a. \x: a. x) [int] 1").unwrap()), "int");
+        assert!(check(r"(/\a. \x: a. x) [bool] 1").is_err());
+    }
+
+    #[test]
+    fn equality_rejects_functions_and_type_variables() {
+        assert!(check(r"(\f: int -> int. f = f) (\x: int. x)").is_err());
+        assert!(check(r"/\a. \x: a. x = x").is_err());
+        assert!(check(r"(\x: int * bool. x = x) (1, true)").is_ok());
+    }
+
+    #[test]
+    fn recursive_bindings_take_their_annotated_types() {
+        let src = r"let rec ev : int -> bool = \n: int. if n = 0 then true else od (n - 1) and od : int -> bool = \n: int. if n = 0 then false else ev (n - 1) in ev";
+        assert_eq!(type_to_string(&check(src).unwrap()), "int -> bool");
+        assert!(check(r"let rec f : int -> bool = \n: int. f in f").is_err());
+    }
+}
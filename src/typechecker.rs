@@ -0,0 +1,523 @@
+use crate::error::Error;
+use crate::parser::declared;
+use crate::systemf::{functional_data, instantiate_fields};
+use crate::types::{ast::*, token::Span};
+
+// Types of a program's statements and body
+pub type ProgramTypes = (Vec<(Ident, Scheme)>, Type);
+
+// Hindley-Milner inference, with let bound variables generalised and unannotated parameters inferred by unification
+pub struct TypeChecker {
+    // Solution of each type variable, if found
+    subst: Vec<Option<Type>>,
+    // Whether each type variable must stand for a type whose values can be compared
+    comparable: Vec<bool>,
+    // Variables in scope and their types, innermost last
+    env: Vec<(String, Scheme)>,
+    // Data types whose values cannot be compared
+    functional: Vec<String>,
+    span: Span
+}
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl TypeChecker {
+    pub fn new() -> TypeChecker {
+        TypeChecker {
+            subst: Vec::new(),
+            comparable: Vec::new(),
+            env: Vec::new(),
+            functional: Vec::new(),
+            span: ((0, 0), (0, 0))
+        }
+    }
+    // New unknown type
+    fn fresh(&mut self) -> Type {
+        self.subst.push(None);
+        self.comparable.push(false);
+        Type::Var(self.subst.len() - 1)
+    }
+    // New unknown type whose values can be compared
+    fn fresh_comparable(&mut self) -> Type {
+        let t = self.fresh();
+        self.comparable[self.subst.len() - 1] = true;
+        t
+    }
+    // Whether values of a type can be compared, requiring the same of its unknown parts
+    fn comparable(&mut self, t: &Type) -> bool {
+        match self.resolve(t) {
+            Type::Var(v) => {
+                self.comparable[v] = true;
+                true
+            },
+            Type::Arrow(_, _) | Type::Forall(_, _) => false,
+            Type::Tuple(tlist) => tlist.iter().all(|t1| self.comparable(t1)),
+            Type::List(t1) => self.comparable(&t1),
+            Type::Data(name, tlist) => !self.functional.contains(&name) && tlist.iter().all(|t1| self.comparable(t1)),
+            _ => true
+        }
+    }
+    // Replace solved variables with their solutions
+    pub fn resolve(&self, t: &Type) -> Type {
+        match t {
+            Type::Var(v) => match &self.subst[*v] {
+                Some(t1) => self.resolve(t1),
+                None => t.clone()
+            },
+            Type::Arrow(t1, t2) => Type::Arrow(Box::new(self.resolve(t1)), Box::new(self.resolve(t2))),
+            Type::Tuple(tlist) => Type::Tuple(tlist.iter().map(|t1| self.resolve(t1)).collect()),
+            Type::List(t1) => Type::List(Box::new(self.resolve(t1))),
+            Type::Data(name, tlist) => Type::Data(name.clone(), tlist.iter().map(|t1| self.resolve(t1)).collect()),
+            _ => t.clone()
+        }
+    }
+    // Whether variable occurs in a resolved type
+    fn occurs(v: usize, t: &Type) -> bool {
+        match t {
+            Type::Var(w) => v == *w,
+            Type::Arrow(t1, t2) => Self::occurs(v, t1) || Self::occurs(v, t2),
+            Type::Tuple(tlist) => tlist.iter().any(|t1| Self::occurs(v, t1)),
+            Type::Data(_, tlist) => tlist.iter().any(|t1| Self::occurs(v, t1)),
+            Type::List(t1) => Self::occurs(v, t1),
+            _ => false
+        }
+    }
+    // Collect unsolved variables of a type in order of appearance
+    fn free_type_vars(&self, t: &Type, out: &mut Vec<usize>) {
+        match self.resolve(t) {
+            Type::Var(v) if !out.contains(&v) => out.push(v),
+            Type::Arrow(t1, t2) => {
+                self.free_type_vars(&t1, out);
+                self.free_type_vars(&t2, out);
+            },
+            Type::Tuple(tlist) => for t1 in tlist.iter() { self.free_type_vars(t1, out) },
+            Type::Data(_, tlist) => for t1 in tlist.iter() { self.free_type_vars(t1, out) },
+            Type::List(t1) => self.free_type_vars(&t1, out),
+            _ => ()
+        }
+    }
+    // Replace variables according to map
+    fn replace(t: &Type, map: &[(usize, Type)]) -> Type {
+        match t {
+            Type::Var(v) => match map.iter().find(|m| m.0 == *v) {
+                Some(m) => m.1.clone(),
+                None => t.clone()
+            },
+            Type::Arrow(t1, t2) => Type::Arrow(Box::new(Self::replace(t1, map)), Box::new(Self::replace(t2, map))),
+            Type::Tuple(tlist) => Type::Tuple(tlist.iter().map(|t1| Self::replace(t1, map)).collect()),
+            Type::List(t1) => Type::List(Box::new(Self::replace(t1, map))),
+            Type::Data(name, tlist) => Type::Data(name.clone(), tlist.iter().map(|t1| Self::replace(t1, map)).collect()),
+            _ => t.clone()
+        }
+    }
+    // Fresh copy of a type scheme for one use
+    fn instantiate(&mut self, s: &Scheme) -> Type {
+        let map: Vec<(usize, Type)> = s.vars.iter().map(|v| (*v, if self.comparable[*v] { self.fresh_comparable() } else { self.fresh() })).collect();
+        Self::replace(&self.resolve(&s.body), &map)
+    }
+    // Quantify over variables that are not fixed by the variables in scope
+    fn generalize(&self, t: &Type) -> Scheme {
+        let mut fixed = Vec::new();
+        for b in self.env.iter() {
+            // Quantified variables of a scheme are not fixed
+            let mut vars = Vec::new();
+            self.free_type_vars(&b.1.body, &mut vars);
+            fixed.extend(vars.into_iter().filter(|v| !b.1.vars.contains(v)));
+        }
+        let mut vars = Vec::new();
+        self.free_type_vars(t, &mut vars);
+        Scheme { vars: vars.into_iter().filter(|v| !fixed.contains(v)).collect(), body: self.resolve(t) }
+    }
+    // Type that is the same at every use
+    fn mono(t: Type) -> Scheme {
+        Scheme { vars: Vec::new(), body: t }
+    }
+    // Make types equal, reporting both whole types if they cannot be
+    fn unify(&mut self, expected: &Type, found: &Type, span: Span) -> Result<(), Error> {
+        match self.unify_parts(expected, found, span) {
+            Err(Error::MismatchError(_, _, _)) => Err(Error::MismatchError(self.resolve(expected), self.resolve(found), span)),
+            r => r
+        }
+    }
+    fn unify_parts(&mut self, expected: &Type, found: &Type, span: Span) -> Result<(), Error> {
+        let (t1, t2) = (self.resolve(expected), self.resolve(found));
+        match (&t1, &t2) {
+            (Type::Var(v), Type::Var(w)) if v == w => Ok(()),
+            (Type::Var(v), t) | (t, Type::Var(v)) => {
+                // Variable cannot stand for a type containing itself
+                if Self::occurs(*v, t) { return Err(Error::InfiniteTypeError(Type::Var(*v), t.clone(), span)) }
+                self.subst[*v] = Some(t.clone());
+                // Variable compared for equality cannot stand for a function
+                if self.comparable[*v] && !self.comparable(t) {
+                    return Err(Error::ExpectedTypeError("a type whose values can be compared", self.resolve(t), span))
+                }
+                Ok(())
+            },
+            (Type::Arrow(a1, r1), Type::Arrow(a2, r2)) => {
+                self.unify_parts(a1, a2, span)?;
+                self.unify_parts(r1, r2, span)
+            },
+            // Tuples of the same size agree componentwise
+            (Type::Tuple(l1), Type::Tuple(l2)) if l1.len() == l2.len() => {
+                for (c1, c2) in l1.iter().zip(l2.iter()) { self.unify_parts(c1, c2, span)? }
+                Ok(())
+            },
+            (Type::List(e1), Type::List(e2)) => self.unify_parts(e1, e2, span),
+            // Same data type with agreeing arguments
+            (Type::Data(n1, l1), Type::Data(n2, l2)) if n1 == n2 && l1.len() == l2.len() => {
+                for (c1, c2) in l1.iter().zip(l2.iter()) { self.unify_parts(c1, c2, span)? }
+                Ok(())
+            },
+            (Type::Int, Type::Int) | (Type::Bool, Type::Bool) | (Type::Unit, Type::Unit) => Ok(()),
+            _ => Err(Error::MismatchError(t1, t2, span))
+        }
+    }
+    // Span of a subexpression, or of the enclosing expression if it has none
+    fn span_of(&self, e: &Expression) -> Span {
+        match e {
+            Expression::SpanExpr(s, _) => *s,
+            _ => self.span
+        }
+    }
+    // Type of the values a pattern matches, and the type of each of its variables
+    fn pattern(&mut self, p: &Pattern) -> (Type, Vec<(String, Type)>) {
+        match p {
+            Pattern::Var(id) => {
+                let t = self.fresh();
+                (t.clone(), id.iter().map(|x| (x.clone(), t.clone())).collect())
+            },
+            // Tuple of exactly as many components as the pattern has
+            Pattern::Tuple(plist) => {
+                let (tlist, vars): (Vec<Type>, Vec<_>) = plist.iter().map(|q| self.pattern(q)).unzip();
+                (Type::Tuple(tlist), vars.into_iter().flatten().collect())
+            }
+        }
+    }
+    // Variables of a pattern matching a value of the given type, generalised like let bound variables
+    fn bind(&mut self, p: &Pattern, t: &Type, span: Span) -> Result<Vec<(String, Scheme)>, Error> {
+        let (tp, vars) = self.pattern(p);
+        self.unify(&tp, t, span)?;
+        Ok(vars.into_iter().map(|(x, tx)| (x, self.generalize(&tx))).collect())
+    }
+    // Function of the given parameters, adding them to scope while checking body
+    fn function(&mut self, params: &[Pattern], types: &[Option<Type>], body: &Expression) -> Result<Type, Error> {
+        let n = self.env.len();
+        // Annotated parameters have their annotation, others are unknown
+        let mut ptypes = Vec::new();
+        for (i, p) in params.iter().enumerate() {
+            let t = match types.get(i) {
+                Some(Some(t)) => t.clone(),
+                _ => self.fresh()
+            };
+            let (tp, vars) = self.pattern(p);
+            if let Err(err) = self.unify(&tp, &t, self.span) {
+                self.env.truncate(n);
+                return Err(err)
+            }
+            self.env.extend(vars.into_iter().map(|(x, tx)| (x, Self::mono(tx))));
+            ptypes.push(t);
+        }
+        let result = self.infer(body);
+        self.env.truncate(n);
+        Ok(ptypes.into_iter().rev().fold(result?, |acc, t| Type::Arrow(Box::new(t), Box::new(acc))))
+    }
+    // List of elements that all have the same type
+    fn list(&mut self, elist: &[Expression]) -> Result<Type, Error> {
+        let a = self.fresh();
+        for ex in elist.iter() {
+            let t = self.infer(ex)?;
+            self.unify(&a, &t, self.span_of(ex))?;
+        }
+        Ok(Type::List(Box::new(a)))
+    }
+    // Data type with unknown arguments for its parameters, and the field types of a constructor
+    fn data(&mut self, d: &DataType, i: usize) -> (Type, Vec<Type>) {
+        let args: Vec<Type> = d.params.iter().map(|_| self.fresh()).collect();
+        let fields = instantiate_fields(d, i, &args);
+        (Type::Data(d.name.clone(), args.into()), fields)
+    }
+    // Constructed data, with fields of the types the constructor expects
+    fn construct(&mut self, d: &DataType, i: usize, elist: &[Expression]) -> Result<Type, Error> {
+        let (t, fields) = self.data(d, i);
+        for (field, ex) in fields.iter().zip(elist.iter()) {
+            let tf = self.infer(ex)?;
+            self.unify(field, &tf, self.span_of(ex))?;
+        }
+        Ok(t)
+    }
+    // Cases bind the fields of the constructor they match, and all bodies have the same type
+    fn cases(&mut self, e1: &Expression, cases: &[Case]) -> Result<Type, Error> {
+        let t1 = self.infer(e1)?;
+        let result = self.fresh();
+        for case in cases.iter() {
+            let vtypes = match &case.constructor {
+                Some((d, i)) => {
+                    let (t, fields) = self.data(d, *i);
+                    self.unify(&t, &t1, self.span_of(e1))?;
+                    fields
+                },
+                None => vec![t1.clone()]
+            };
+            let n = self.env.len();
+            for (var, t) in case.vars.iter().zip(vtypes) {
+                if let Some(name) = var { self.env.push((name.clone(), Self::mono(t))) };
+            }
+            let tb = self.infer(&case.body);
+            self.env.truncate(n);
+            self.unify(&result, &tb?, self.span_of(&case.body))?;
+        }
+        Ok(result)
+    }
+    fn infer(&mut self, e: &Expression) -> Result<Type, Error> {
+        match e {
+            Expression::ValExpr(v) => match v {
+                Value::Number(_) => Ok(Type::Int),
+                Value::Boolean(_) => Ok(Type::Bool),
+                Value::Unit => Ok(Type::Unit),
+                // Innermost binding of variable, instantiated for this use
+                Value::Identifier(x) => match self.env.iter().rev().find(|b| &b.0 == x) {
+                    Some(b) => {
+                        let s = b.1.clone();
+                        Ok(self.instantiate(&s))
+                    },
+                    None => Err(Error::UnboundError(x.clone(), self.span))
+                },
+                Value::Closure(params, body, _) => self.function(params, &[], body.as_ref()),
+                Value::Tuple(elist) => Ok(Type::Tuple(elist.iter().map(|ex| self.infer(ex)).collect::<Result<_, _>>()?)),
+                Value::List(elist) => self.list(elist),
+                Value::Data(d, i, elist) => self.construct(d, *i, elist)
+            },
+            Expression::ListExpr(elist) => self.list(elist),
+            Expression::TupleExpr(elist) => Ok(Type::Tuple(elist.iter().map(|ex| self.infer(ex)).collect::<Result<_, _>>()?)),
+            Expression::ConstructExpr(d, i, elist) => self.construct(d, *i, elist),
+            Expression::MatchExpr(e1, cases) => self.cases(e1.as_ref(), cases),
+            Expression::ProjExpr(e1, i, n) => {
+                let t1 = self.infer(e1.as_ref())?;
+                match (self.resolve(&t1), n) {
+                    // Size is known from the projection, as in patterns and fst and snd, so the tuple has exactly that size
+                    (Type::Tuple(_) | Type::Var(_), Some(n)) if i < n => {
+                        let tlist: Vec<Type> = (0..*n).map(|_| self.fresh()).collect();
+                        self.unify(&Type::Tuple(tlist.clone()), &t1, self.span_of(e1.as_ref()))?;
+                        Ok(tlist[*i].clone())
+                    },
+                    // Component of a tuple type
+                    (Type::Tuple(tlist), None) if *i < tlist.len() => Ok(tlist[*i].clone()),
+                    (Type::Tuple(_), _) => Err(Error::ExpectedTypeError("a tuple with a component at this index", self.resolve(&t1), self.span_of(e1.as_ref()))),
+                    (Type::Var(_), None) => Err(Error::ExpectedTypeError("a tuple of known size", self.resolve(&t1), self.span_of(e1.as_ref()))),
+                    (t, _) => Err(Error::ExpectedTypeError("a tuple", t, self.span_of(e1.as_ref())))
+                }
+            },
+            Expression::FuncExpr(params, types, body) => self.function(params, types, body.as_ref()),
+            Expression::ApplicationExpr(alist) => {
+                let mut tf = self.infer(&alist[0])?;
+                for arg in alist[1..].iter() {
+                    let ta = self.infer(arg)?;
+                    tf = match self.resolve(&tf) {
+                        // Argument must match parameter
+                        Type::Arrow(p, r) => {
+                            self.unify(&p, &ta, self.span_of(arg))?;
+                            *r
+                        },
+                        // Unknown function type is determined by argument
+                        Type::Var(_) => {
+                            let r = self.fresh();
+                            self.unify(&tf, &Type::Arrow(Box::new(ta), Box::new(r.clone())), self.span_of(arg))?;
+                            r
+                        },
+                        // Only functions can be applied
+                        t => {
+                            let r = self.fresh();
+                            return Err(Error::MismatchError(Type::Arrow(Box::new(ta), Box::new(r)), t, self.span_of(&alist[0])))
+                        }
+                    }
+                }
+                Ok(tf)
+            },
+            Expression::IfExpr(c, t, f) => {
+                // Condition is a boolean and branches agree
+                let tc = self.infer(c.as_ref())?;
+                self.unify(&Type::Bool, &tc, self.span_of(c.as_ref()))?;
+                let tt = self.infer(t.as_ref())?;
+                let tf = self.infer(f.as_ref())?;
+                self.unify(&tt, &tf, self.span_of(f.as_ref()))?;
+                Ok(tt)
+            },
+            Expression::LetExpr(id, e1, body) => {
+                let t1 = self.infer(e1.as_ref())?;
+                // Pattern is bound in body only, generalised so each use may differ
+                let n = self.env.len();
+                let vars = self.bind(id, &t1, self.span_of(e1.as_ref()))?;
+                self.env.extend(vars);
+                let result = self.infer(body.as_ref());
+                self.env.truncate(n);
+                result
+            },
+            Expression::FixExpr(flist, i) => {
+                // Each function takes every function of the group and returns its own
+                let tlist: Vec<Type> = flist.iter().map(|_| self.fresh()).collect();
+                for (j, f) in flist.iter().enumerate() {
+                    let expected = tlist.iter().rev().fold(tlist[j].clone(), |acc, t| Type::Arrow(Box::new(t.clone()), Box::new(acc)));
+                    let tf = self.infer(f)?;
+                    self.unify(&expected, &tf, self.span_of(f))?;
+                }
+                Ok(tlist[*i].clone())
+            },
+            Expression::UopExpr(op, e1) => {
+                // Operand and result types
+                let (t, result) = match op {
+                    Uop::NegUop => (Type::Int, Type::Int),
+                    Uop::NotUop => (Type::Bool, Type::Bool),
+                    Uop::HeadUop => {
+                        let a = self.fresh();
+                        (Type::List(Box::new(a.clone())), a)
+                    },
+                    Uop::TailUop => {
+                        let a = Type::List(Box::new(self.fresh()));
+                        (a.clone(), a)
+                    },
+                    Uop::IsNilUop => (Type::List(Box::new(self.fresh())), Type::Bool)
+                };
+                let t1 = self.infer(e1.as_ref())?;
+                self.unify(&t, &t1, self.span_of(e1.as_ref()))?;
+                Ok(result)
+            },
+            Expression::BopExpr(op, e1, e2) => {
+                // Operand and result types, equality compares any two values of the same type without functions
+                let (l, r, result) = match op {
+                    Bop::PlusBop | Bop::MinusBop | Bop::TimesBop | Bop::DivBop => (Type::Int, Type::Int, Type::Int),
+                    Bop::GtBop | Bop::GteBop | Bop::LtBop | Bop::LteBop => (Type::Int, Type::Int, Type::Bool),
+                    Bop::EqBop => {
+                        let a = self.fresh_comparable();
+                        (a.clone(), a, Type::Bool)
+                    },
+                    Bop::AndBop | Bop::OrBop | Bop::XorBop => (Type::Bool, Type::Bool, Type::Bool),
+                    // Element is prepended to a list of its type
+                    Bop::ConsBop => {
+                        let a = self.fresh();
+                        let list = Type::List(Box::new(a.clone()));
+                        (a, list.clone(), list)
+                    }
+                };
+                let t1 = self.infer(e1.as_ref())?;
+                self.unify(&l, &t1, self.span_of(e1.as_ref()))?;
+                let t2 = self.infer(e2.as_ref())?;
+                self.unify(&r, &t2, self.span_of(e2.as_ref()))?;
+                Ok(result)
+            },
+            Expression::SpanExpr(span, ex) => {
+                // Track innermost span for error reporting
+                let outer_span = std::mem::replace(&mut self.span, *span);
+                let result = self.infer(ex.as_ref());
+                self.span = outer_span;
+                result
+            },
+            // Explicit types are ignored, inferring the type of the erased program
+            Expression::EnvExpr(_, ex) | Expression::TypeFuncExpr(_, ex) | Expression::TypeAppExpr(ex, _) | Expression::DeclExpr(_, ex) => self.infer(ex.as_ref()),
+            Expression::ThunkExpr(cell) => self.infer(&cell.borrow()),
+            // Errors were already reported by the parser
+            Expression::ErrorExpr => Ok(self.fresh())
+        }
+    }
+    // Infer types of each statement and the body
+    pub fn check_program(&mut self, prog: &Program) -> Result<ProgramTypes, Error> {
+        self.env.clear();
+        self.functional = functional_data(prog);
+        let mut stmts = Vec::new();
+        // Declarations have no type
+        for (id, e) in prog.0.iter().filter(|s| declared(s).is_none()) {
+            let t = self.infer(e)?;
+            // Statements are generalised like let expressions
+            let vars = self.bind(id, &t, self.span_of(e))?;
+            self.env.extend(vars.iter().cloned());
+            // Statement binding no name is listed by its whole type
+            if *id == Pattern::Var(None) { stmts.push((None, self.generalize(&t))) };
+            stmts.extend(vars.into_iter().map(|(x, s)| (Some(x), s)));
+        }
+        let body = self.infer(&prog.1)?;
+        Ok((stmts, self.resolve(&body)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, printing::{scheme_to_string, type_to_string}};
+
+    fn check(src: &str) -> Result<ProgramTypes, Error> {
+        let tokens = Lexer::new().generate(src).unwrap();
+        let prog = Parser::new(false, false).parse_program(tokens).unwrap();
+        TypeChecker::new().check_program(&prog)
+    }
+
+    fn body_type(src: &str) -> String {
+        type_to_string(&check(src).unwrap().1)
+    }
+
+    #[test]
+    fn let_bound_values_are_generalised() {
+        assert_eq!(body_type(r"let id = \x. x in if id true then id 1 else 2"), "int");
+        let (stmts, _) = check(r"let id = \x. x in id").unwrap();
+        assert_eq!(scheme_to_string(&stmts[0].1), "forall a. a -> a");
+    }
+
+    #[test]
+    fn lambda_bound_values_are_not_generalised() {
+        // Variables of the parameter's type are fixed in the let, so y is used at one type
+        assert!(matches!(check(r"\x. let y = x in if y true then y 1 else 2"), Err(Error::MismatchError(_, _, _))));
+        assert!(check(r"\f. if f true then f 1 else 2").is_err());
+    }
+
+    #[test]
+    fn occurs_check_rejects_self_application() {
+        assert!(matches!(check(r"\x. x x"), Err(Error::InfiniteTypeError(_, _, _))));
+    }
+
+    #[test]
+    fn recursive_function_type_is_inferred() {
+        assert_eq!(body_type(r"let rec f = \n. if n = 0 then 1 else n * (f (n - 1)) in f"), "int -> int");
+    }
+
+    #[test]
+    fn equality_rejects_functions() {
+        assert!(matches!(check(r"(\x. x) = (\x. x)"), Err(Error::ExpectedTypeError(_, _, _))));
+        assert!(check(r"let eq = \x y. x = y in eq (\x. x) (\x. x)").is_err());
+        assert!(check(r"type box = Box (int -> int) in Box (\x. x) = Box (\x. x)").is_err());
+        assert_eq!(body_type(r"let eq = \x y. x = y in eq (1, [true]) (1, [false])"), "bool");
+    }
+
+    #[test]
+    fn projection_size_is_respected() {
+        assert!(check("fst (1, 2, 3)").is_err());
+        assert!(check(r"(\p. fst p) (1, 2, 3)").is_err());
+        assert_eq!(body_type(r"(\p. snd p) (1, true)"), "bool");
+    }
+
+    #[test]
+    fn list_elements_share_one_type() {
+        assert_eq!(body_type("[[1], []]"), "int list list");
+        assert_eq!(body_type("(nil, 1 :: nil, isnil [true])"), "a list * int list * bool");
+        assert!(matches!(check("[1, true]"), Err(Error::MismatchError(_, _, _))));
+        assert!(check("1 :: [true]").is_err());
+    }
+
+    #[test]
+    fn list_keywords_have_polymorphic_types() {
+        let (stmts, _) = check("let h = head in let c = cons in h").unwrap();
+        assert_eq!(scheme_to_string(&stmts[0].1), "forall a. a list -> a");
+        assert_eq!(scheme_to_string(&stmts[1].1), "forall a. a -> a list -> a list");
+    }
+
+    #[test]
+    fn constructors_infer_the_parameters_of_their_type() {
+        assert_eq!(body_type("type option = None | Some a in (Some 1, None)"), "int option * a option");
+        assert_eq!(body_type(r"type option = None | Some a in \o. match o with None -> 0 | Some x -> x"), "int option -> int");
+    }
+
+    #[test]
+    fn match_cases_agree_on_their_types() {
+        assert!(matches!(check("type t = A | B in match A with A -> 1 | B -> true"), Err(Error::MismatchError(_, _, _))));
+        assert!(check("type t = A in match 1 with A -> 0").is_err());
+    }
+}
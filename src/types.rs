@@ -1,38 +1,131 @@
 pub mod ast {
+    use std::{cell::RefCell, rc::Rc};
     use crate::evaluator::Environment;
+    use super::token::Span;
 
     pub type Ident = Option<String>;
 
     pub type Program = (Vec<Statement>, Expression);
 
-    pub type Statement = (Ident, Expression);
+    pub type Statement = (Pattern, Expression);
 
-    #[derive(Clone)]
+    // Variable, or tuple of patterns each binding one component, bound by function parameters and let bindings
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum Pattern {
+        Var(Ident),
+        Tuple(Vec<Pattern>)
+    }
+    impl Pattern {
+        // Variables bound by the pattern, from left to right
+        pub fn vars(&self) -> Vec<&String> {
+            match self {
+                Pattern::Var(id) => id.iter().collect(),
+                Pattern::Tuple(plist) => plist.iter().flat_map(|p| p.vars()).collect()
+            }
+        }
+        // Binder slots of the pattern, from left to right, for renaming in place
+        pub fn idents_mut(&mut self) -> Vec<&mut Ident> {
+            match self {
+                Pattern::Var(id) => vec![id],
+                Pattern::Tuple(plist) => plist.iter_mut().flat_map(|p| p.idents_mut()).collect()
+            }
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    #[allow(clippy::enum_variant_names)]
     pub enum Expression {
         UopExpr(Uop, Box<Expression>),
         BopExpr(Bop, Box<Expression>, Box<Expression>),
         ApplicationExpr(Vec<Expression>),
-        FuncExpr(Vec<Ident>, Box<Expression>),
+        // Parameters, optional parameter type annotations, body
+        FuncExpr(Vec<Pattern>, Vec<Option<Type>>, Box<Expression>),
+        IfExpr(Box<Expression>, Box<Expression>, Box<Expression>),
+        FixExpr(Vec<Expression>, usize),
+        LetExpr(Pattern, Box<Expression>, Box<Expression>),
+        TupleExpr(Vec<Expression>),
+        // Tuple, component index, number of components if known from the syntax
+        ProjExpr(Box<Expression>, usize, Option<usize>),
+        ListExpr(Vec<Expression>),
+        // Declaration, constructor index, fields
+        ConstructExpr(Rc<DataType>, usize, Vec<Expression>),
+        // Scrutinee, cases tried in order
+        MatchExpr(Box<Expression>, Vec<Case>),
+        // Type variable, body
+        TypeFuncExpr(String, Box<Expression>),
+        TypeAppExpr(Box<Expression>, Type),
+        // Data type declaration, body in which its constructors are in scope
+        DeclExpr(Rc<DataType>, Box<Expression>),
         EnvExpr(Environment, Box<Expression>),
+        ThunkExpr(Rc<RefCell<Expression>>),
+        SpanExpr(Span, Box<Expression>),
+        ErrorExpr,
         ValExpr(Value)
     }
 
-    #[derive(Clone)]
+    #[derive(Clone, Debug)]
     pub enum Value {
         Identifier(String),
         Number(i128),
         Boolean(bool),
-        Closure(Vec<Ident>, Box<Expression>, Environment),
+        Closure(Vec<Pattern>, Box<Expression>, Environment),
+        Tuple(Vec<Expression>),
+        List(Vec<Expression>),
+        Data(Rc<DataType>, usize, Vec<Expression>),
         Unit
     }
 
-    #[derive(Clone)]
+    // Algebraic data type, with parameters and the field types of each constructor
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct DataType {
+        pub name: String,
+        pub params: Vec<String>,
+        pub constructors: Vec<(String, Vec<Type>)>
+    }
+
+    // Constructor and variables bound to its fields, or no constructor to bind the whole value to a single variable
+    #[derive(Clone, Debug)]
+    pub struct Case {
+        pub constructor: Option<(Rc<DataType>, usize)>,
+        pub vars: Vec<Ident>,
+        pub body: Expression
+    }
+
+    // Type of an expression, variables stand for types not yet known
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum Type {
+        Int,
+        Bool,
+        Unit,
+        Arrow(Box<Type>, Box<Type>),
+        Tuple(Vec<Type>),
+        List(Box<Type>),
+        // Name of an algebraic data type, arguments for its parameters
+        Data(String, Box<[Type]>),
+        Var(usize),
+        // Type variable written in the program, bound by a type abstraction or forall
+        Named(String),
+        Forall(String, Box<Type>)
+    }
+
+    // Type with variables that can be instantiated differently at each use
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Scheme {
+        pub vars: Vec<usize>,
+        pub body: Type
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
     pub enum Uop {
         NegUop,
         NotUop,
+        HeadUop,
+        TailUop,
+        IsNilUop,
     }
 
-    #[derive(Clone)]
+    #[derive(Clone, Debug, PartialEq)]
+    #[allow(clippy::enum_variant_names)]
     pub enum Bop {
         PlusBop,
         MinusBop,
@@ -45,13 +138,15 @@ pub mod ast {
         EqBop,
         AndBop,
         OrBop,
-        XorBop
+        XorBop,
+        ConsBop
     }
 }
 
 pub mod token {
     // Token variant
-    #[derive(Clone, PartialEq)]
+    #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+    #[allow(clippy::upper_case_acronyms)]
     pub enum Variant {
         Lambda,
         Dot,
@@ -76,6 +171,29 @@ pub mod token {
         Xor,
         Let,
         In,
+        If,
+        Then,
+        Else,
+        Rec,
+        RecAnd,
+        Fix,
+        Colon,
+        Arrow,
+        TypeLambda,
+        LBracket,
+        RBracket,
+        Comma,
+        Fst,
+        Snd,
+        DoubleColon,
+        Cons,
+        Nil,
+        Head,
+        Tail,
+        IsNil,
+        Type,
+        Match,
+        With,
         Unit,
     }
 
@@ -87,10 +205,43 @@ pub mod token {
         Boolean(bool),
         None
     }
-    // Position stores row and column
+    // Position stores row and column, both starting at zero
     pub type Position = (usize, usize);
+    // Span stores start and (exclusive) end position
+    pub type Span = (Position, Position);
     // Tuple of both
-    pub type Token = (Variant, TokenValue, Position);
+    pub type Token = (Variant, TokenValue, Span);
+}
+
+pub mod debruijn {
+    use std::rc::Rc;
+    use super::ast::{Uop, Bop, DataType};
+
+    // Case of a match, with its constructor, the number of variables it binds, and its body
+    pub type TermCase = (Option<(Rc<DataType>, usize)>, usize, Term);
+
+    // Nameless term, variables refer to binders by how many binders lie in between
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum Term {
+        Var(usize),
+        Free(String),
+        Lam(Box<Term>),
+        App(Box<Term>, Box<Term>),
+        Let(Box<Term>, Box<Term>),
+        Fix(Vec<Term>, usize),
+        Tuple(Vec<Term>),
+        Proj(Box<Term>, usize),
+        List(Vec<Term>),
+        Construct(Rc<DataType>, usize, Vec<Term>),
+        Match(Box<Term>, Vec<TermCase>),
+        If(Box<Term>, Box<Term>, Box<Term>),
+        Uop(Uop, Box<Term>),
+        Bop(Bop, Box<Term>, Box<Term>),
+        Num(i128),
+        Bool(bool),
+        Unit,
+        Error
+    }
 }
 
 pub mod eval {